@@ -14,11 +14,30 @@ export class HttpError extends Error {
 
 export interface ResponseError {
     status_code: number,
-    message: string
+    message: string,
+    code?: string
 }
 
-export function handle_error(error: AxiosError): Promise<ResponseError> {
-    return Promise.reject<ResponseError>({ status_code: error.response?.status, message: error.response?.data });
+/** Error body of the API, described by RFC 7807. */
+interface Problem {
+    status: number,
+    code: string,
+    detail: string,
+    request_id?: string
+}
+
+export async function handle_error(error: AxiosError<Problem | Blob>): Promise<ResponseError> {
+    const data = error.response?.data;
+    // The resources client reads every body as a blob, error bodies included.
+    const problem: Problem | undefined = data instanceof Blob
+        ? await data.text().then(text => JSON.parse(text)).catch(() => undefined)
+        : data;
+
+    return Promise.reject<ResponseError>({
+        status_code: error.response?.status,
+        message: problem?.detail ?? error.message,
+        code: problem?.code
+    });
 }
 
 const debug = import.meta.hot;
@@ -1,17 +1,112 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
 use askama_axum::Template;
 use rust_embed::RustEmbed;
 
+/// Where the build puts the assets, relative to `dist/` and to the site root.
+const ASSETS_DIR: &str = "resources/assets/";
+
 #[derive(RustEmbed)]
 #[folder = "dist/resources/assets/"]
 pub struct Assets;
 
+/// A file of the build with what it pulls in, as listed by the Vite manifest.
+#[derive(Debug, Default, serde::Deserialize)]
+struct Chunk {
+    file: String,
+    #[serde(default)]
+    css: Vec<String>,
+    #[serde(default)]
+    assets: Vec<String>,
+}
+
+/// Vite build manifest, mapping sources to the files built from them.
+///
+/// Every file it lists carries a content hash in its name.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    chunks: HashMap<String, Chunk>,
+}
+
+impl Manifest {
+    fn parse(content: &[u8]) -> Result<Self, serde_json::Error> {
+        Ok(Manifest {
+            chunks: serde_json::from_slice(content)?,
+        })
+    }
+
+    /// Whether the asset, named relative to [`Assets`], changes its name with its content.
+    pub fn is_fingerprinted(&self, name: &str) -> bool {
+        self.chunks.values().any(|chunk| {
+            std::iter::once(&chunk.file)
+                .chain(&chunk.css)
+                .chain(&chunk.assets)
+                .any(|file| file.strip_prefix(ASSETS_DIR) == Some(name))
+        })
+    }
+}
+
+/// Manifest of the embedded build, empty when it has none, as in development.
+pub fn manifest() -> &'static Manifest {
+    static MANIFEST: OnceLock<Manifest> = OnceLock::new();
+
+    MANIFEST.get_or_init(|| {
+        Assets::get("manifest.json")
+            .and_then(|content| Manifest::parse(&content.data).ok())
+            .unwrap_or_default()
+    })
+}
+
 #[derive(Template)]
 #[template(path = "base.html")]
 pub struct BaseTemplate<'a> {
     pub view: &'a str,
 }
 
+impl BaseTemplate<'_> {
+    fn entry(&self) -> Option<&'static Chunk> {
+        manifest().chunks.get("index.html")
+    }
+
+    fn script(&self) -> String {
+        match self.entry() {
+            Some(entry) => format!("/{}", entry.file),
+            None => format!("/{}index.js", ASSETS_DIR),
+        }
+    }
+
+    fn styles(&self) -> Vec<String> {
+        match self.entry() {
+            Some(entry) => entry.css.iter().map(|css| format!("/{}", css)).collect(),
+            None => vec![format!("/{}index.css", ASSETS_DIR)],
+        }
+    }
+}
+
 #[test]
 fn test_render() {
     println!("{}", BaseTemplate { view: "home" }.render().unwrap());
 }
+
+#[test]
+fn test_manifest() {
+    let manifest = Manifest::parse(
+        br#"{
+            "index.html": {
+                "file": "resources/assets/index-BRdq_3tB.js",
+                "src": "index.html",
+                "isEntry": true,
+                "css": ["resources/assets/index-C-4fn3zA.css"],
+                "assets": ["resources/assets/inter-variable-D0v-EhnZ.woff2"]
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert!(manifest.is_fingerprinted("index-BRdq_3tB.js"));
+    assert!(manifest.is_fingerprinted("index-C-4fn3zA.css"));
+    assert!(manifest.is_fingerprinted("inter-variable-D0v-EhnZ.woff2"));
+    assert!(!manifest.is_fingerprinted("app-settings.css"));
+    assert!(!manifest.is_fingerprinted("manifest.json"));
+}
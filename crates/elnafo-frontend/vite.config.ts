@@ -9,11 +9,13 @@ export default defineConfig({
         vueJsx(),
     ],
     build: {
+        // Read by the server to link the entry and to tell which files are fingerprinted.
+        manifest: "resources/assets/manifest.json",
         rollupOptions: {
             output: {
-                entryFileNames: "resources/assets/[name].js",
-                assetFileNames: "resources/assets/[name][extname]",
-                chunkFileNames: "resources/assets/[name].js"
+                entryFileNames: "resources/assets/[name]-[hash].js",
+                assetFileNames: "resources/assets/[name]-[hash][extname]",
+                chunkFileNames: "resources/assets/[name]-[hash].js"
             }
         }
     },
@@ -61,6 +61,7 @@
                     fenix.packages.${system}.complete.toolchain 
                     pkgs.ripgrep
                     pkgs.postgresql
+                    pkgs.sqlite
                     pkgs.diesel-cli
                     pkgs.cargo-watch
                     pkgs.mold-wrapped
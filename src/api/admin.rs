@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::{Extension, Json};
+
+use crate::db::user::User;
+use crate::state::AppState;
+
+use super::errors::{ApiError, Problem};
+use super::middleware::admin;
+
+pub mod schema {
+    /// Connection pool usage.
+    #[derive(serde::Serialize, utoipa::ToSchema)]
+    pub struct Pool {
+        /// Configured maximum number of connections.
+        pub max_size: usize,
+        /// Connections currently open.
+        pub size: usize,
+        /// Open connections not in use.
+        pub available: usize,
+        /// Requests waiting for a connection.
+        pub waiting: usize,
+        /// Read replicas, in configuration order.
+        pub replicas: Vec<Replica>,
+    }
+
+    /// Read replica usage.
+    #[derive(serde::Serialize, utoipa::ToSchema)]
+    pub struct Replica {
+        /// Whether lookups are sent to it; a replica failing to connect is retried later.
+        pub healthy: bool,
+        pub size: usize,
+        pub available: usize,
+        pub waiting: usize,
+    }
+}
+
+#[utoipa::path(get, path = "/api/admin/database",
+    security(("token" = [])),
+    responses((status = 200, body = Pool), (status = 403, response = Problem))
+)]
+pub async fn database(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<schema::Pool>, ApiError> {
+    admin(&user)?;
+
+    let status = state.database.status();
+
+    Ok(Json(schema::Pool {
+        max_size: status.max_size,
+        size: status.size,
+        available: status.available,
+        waiting: status.waiting,
+        replicas: state
+            .database
+            .replicas
+            .replicas
+            .iter()
+            .map(|replica| {
+                let status = replica.connections.status();
+
+                schema::Replica {
+                    healthy: replica.is_healthy(),
+                    size: status.size,
+                    available: status.available,
+                    waiting: status.waiting,
+                }
+            })
+            .collect(),
+    }))
+}
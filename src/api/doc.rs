@@ -1,12 +1,16 @@
 use utoipa::{
     openapi::{
-        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
         Components,
     },
     Modify, OpenApi,
 };
 
+use super::admin;
 use super::errors;
+use super::files;
+use super::settings;
+use super::tus;
 use super::user;
 
 #[derive(OpenApi)]
@@ -20,19 +24,51 @@ use super::user;
         user::logout,
         user::profile,
         user::current,
-        user::avatar
+        user::avatar,
+        user::avatar_style,
+        files::list,
+        files::public,
+        files::info,
+        files::upload,
+        files::visibility,
+        files::remove,
+        tus::options,
+        tus::create,
+        tus::status,
+        tus::append,
+        tus::terminate,
+        settings::public,
+        settings::get,
+        settings::update,
+        settings::history,
+        admin::database
+    ),
+    components(
+        schemas(
+            errors::Problem,
+            errors::FieldError,
+            user::schema::NewUser,
+            user::schema::User,
+            user::schema::Me,
+            user::schema::RemoveUser,
+            user::schema::LoginUser,
+            user::schema::Avatar,
+            user::schema::AvatarStyle,
+            crate::resources::generated::Style,
+            user::schema::Image,
+            files::schema::File,
+            files::schema::SetVisibility,
+            files::schema::Upload,
+            crate::db::file::Visibility,
+            settings::schema::Public,
+            settings::schema::Change,
+            crate::settings::Settings,
+            crate::config::Registration,
+            admin::schema::Pool,
+            admin::schema::Replica
+        ),
+        responses(errors::Problem)
     ),
-    components(schemas(
-        crate::db::errors::DatabaseError,
-        user::UserError,
-        user::schema::NewUser,
-        user::schema::User,
-        user::schema::RemoveUser,
-        user::schema::LoginUser,
-        user::schema::Avatar,
-        user::schema::Image,
-        errors::ApiError
-    )),
     modifiers(&SecurityAddon)
 )]
 pub struct ApiDoc;
@@ -1,18 +1,95 @@
-use std::any::Any;
-
-use axum::{http::StatusCode, response::IntoResponse, Json};
-use serde_json::json;
+use axum::extract::multipart::MultipartRejection;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 
 use crate::db::errors::DatabaseError;
+use crate::repository::errors::RepositoryError;
+use crate::storage::errors::StorageError;
 
+use super::files::FileError;
+use super::middleware::current_request_id;
+use super::settings::SettingsError;
 use super::user::UserError;
 
-#[derive(Debug, utoipa::ToSchema)]
+/// Body of every error response, described by RFC 7807.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema, utoipa::ToResponse)]
+#[response(
+    description = "Error described by RFC 7807",
+    content_type = "application/problem+json",
+    headers(("x-request-id" = String, description = "Id of the request, also in the body"))
+)]
+pub struct Problem {
+    /// Always `about:blank`, errors are told apart by `code`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Reason phrase of the status.
+    pub title: String,
+    pub status: u16,
+    /// Stable identifier of the error for clients to match on, such as `user.not_found`.
+    pub code: String,
+    /// Explanation meant for people, which may change between versions.
+    pub detail: String,
+    /// Id of the request, to find it in the server logs.
+    pub request_id: Option<String>,
+    /// Rejected fields of the request, when there are any.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// A rejected field of the request, with the reason.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &str, detail: impl ToString) -> Self {
+        Problem {
+            kind: String::from("about:blank"),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            code: code.to_string(),
+            detail: detail.to_string(),
+            request_id: current_request_id(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> axum::response::Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            axum::Json(self),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Debug)]
 pub enum ApiError {
     Database(DatabaseError),
     AuthError(AuthError),
     ReadContent,
+    /// Request body that is not the expected JSON.
+    Body(JsonRejection),
+    /// Path parameters that do not parse, such as an invalid id.
+    Path(PathRejection),
+    /// Query string that does not parse.
+    QueryString(QueryRejection),
+    /// Request that is not a readable multipart form.
+    Multipart(MultipartRejection),
     Query(UserError),
+    Storage(StorageError),
+    Upload(UploadError),
+    File(FileError),
+    Settings(SettingsError),
 }
 
 impl std::error::Error for ApiError {}
@@ -23,7 +100,15 @@ impl std::fmt::Display for ApiError {
             Self::Database(ref e) => e.fmt(f),
             Self::AuthError(e) => write!(f, "Authentication error occured: {}", e),
             Self::ReadContent => write!(f, "Failed to read body content"),
+            Self::Body(ref e) => write!(f, "{}", e.body_text()),
+            Self::Path(ref e) => write!(f, "{}", e.body_text()),
+            Self::QueryString(ref e) => write!(f, "{}", e.body_text()),
+            Self::Multipart(ref e) => write!(f, "{}", e.body_text()),
             Self::Query(ref e) => e.fmt(f),
+            Self::Storage(ref e) => e.fmt(f),
+            Self::Upload(ref e) => e.fmt(f),
+            Self::File(ref e) => e.fmt(f),
+            Self::Settings(ref e) => e.fmt(f),
         }
     }
 }
@@ -34,12 +119,62 @@ impl From<DatabaseError> for ApiError {
     }
 }
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
-        let status = match self {
-            Self::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        Self::Body(e)
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(e: PathRejection) -> Self {
+        Self::Path(e)
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> Self {
+        Self::QueryString(e)
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(e: MultipartRejection) -> Self {
+        Self::Multipart(e)
+    }
+}
+
+impl From<RepositoryError> for ApiError {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::Exists => Self::Query(UserError::Exists),
+            RepositoryError::RegistrationClosed => Self::Query(UserError::RegistrationClosed),
+            RepositoryError::Database(e) => Self::Database(e),
+        }
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::Database(e) => Self::Database(e),
+            e => Self::Storage(e),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Database(ref e) => rejected_query(e)
+                .map(|(status, _, _)| status)
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::AuthError(AuthError::Forbidden) => StatusCode::FORBIDDEN,
             Self::AuthError(_) => StatusCode::UNAUTHORIZED,
             Self::ReadContent => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Body(ref e) => e.status(),
+            Self::Path(ref e) => e.status(),
+            Self::QueryString(ref e) => e.status(),
+            Self::Multipart(ref e) => e.status(),
             Self::Query(ref e) => match e {
                 UserError::Exists => StatusCode::CONFLICT,
                 UserError::HashPassword | UserError::ParseUuid => StatusCode::INTERNAL_SERVER_ERROR,
@@ -47,10 +182,116 @@ impl IntoResponse for ApiError {
                 | UserError::InvalidCredentials
                 | UserError::Unauthorized => StatusCode::UNAUTHORIZED,
                 UserError::NotFound => StatusCode::NOT_FOUND,
+                UserError::RegistrationClosed => StatusCode::FORBIDDEN,
             },
-        };
+            Self::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Upload(ref e) => match e {
+                UploadError::Missing => StatusCode::BAD_REQUEST,
+                UploadError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+                UploadError::UnsupportedFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                UploadError::Dimensions | UploadError::Corrupted => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+                UploadError::Encode => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::File(ref e) => match e {
+                FileError::NotFound => StatusCode::NOT_FOUND,
+                FileError::QuotaExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+                FileError::InvalidMetadata => StatusCode::BAD_REQUEST,
+                FileError::OffsetMismatch => StatusCode::CONFLICT,
+                FileError::UnsupportedVersion => StatusCode::PRECONDITION_FAILED,
+                FileError::UnsupportedContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            },
+            Self::Settings(ref e) => match e {
+                SettingsError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            },
+        }
+    }
+
+    /// Stable identifier of the error, see [`Problem::code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Database(ref e) => rejected_query(e)
+                .map(|(_, code, _)| code)
+                .unwrap_or("database.error"),
+            Self::AuthError(ref e) => e.code(),
+            Self::ReadContent => "request.unreadable",
+            Self::Body(_) => "request.invalid_body",
+            Self::Path(_) => "request.invalid_path",
+            Self::QueryString(_) => "request.invalid_query",
+            Self::Multipart(_) => "request.invalid_multipart",
+            Self::Query(ref e) => e.code(),
+            Self::Storage(_) => "storage.error",
+            Self::Upload(ref e) => e.code(),
+            Self::File(ref e) => e.code(),
+            Self::Settings(ref e) => e.code(),
+        }
+    }
+}
 
-        (status, format!("{}", self)).into_response()
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.status();
+
+        // Details of failures on our side stay in the logs, clients only get the id to find them.
+        if status.is_server_error() {
+            let mut problem = Problem::new(status, self.code(), "Internal server error");
+            let id = problem
+                .request_id
+                .get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+            tracing::error!(request_id = %id, "{}: {}", problem.code, self);
+
+            return problem.into_response();
+        }
+
+        if let Self::Database(ref e) = self {
+            if let Some((status, code, detail)) = rejected_query(e) {
+                let problem = Problem::new(status, code, detail);
+                tracing::warn!(request_id = ?problem.request_id, "{}: {}", code, e);
+
+                return problem.into_response();
+            }
+        }
+
+        let mut problem = Problem::new(status, self.code(), &self);
+
+        if let Self::Settings(SettingsError::Invalid(ref invalid)) = self {
+            problem.errors = invalid
+                .iter()
+                .map(|invalid| FieldError {
+                    field: invalid.key.to_owned(),
+                    message: invalid.message.to_owned(),
+                })
+                .collect();
+        }
+
+        problem.into_response()
+    }
+}
+
+/// Queries the database refused because of the request rather than a failure,
+/// with their status, code and a description that does not reveal the schema.
+fn rejected_query(e: &DatabaseError) -> Option<(StatusCode, &'static str, &'static str)> {
+    match e {
+        DatabaseError::Query(DieselError::NotFound) => Some((
+            StatusCode::NOT_FOUND,
+            "database.not_found",
+            "The record does not exist",
+        )),
+        DatabaseError::Query(DieselError::DatabaseError(kind, _)) => match kind {
+            DatabaseErrorKind::UniqueViolation => Some((
+                StatusCode::CONFLICT,
+                "database.conflict",
+                "The record conflicts with an existing one",
+            )),
+            DatabaseErrorKind::ForeignKeyViolation => Some((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "database.invalid_reference",
+                "The record refers to one that does not exist",
+            )),
+            _ => None,
+        },
+        _ => None,
     }
 }
 
@@ -61,6 +302,7 @@ pub enum AuthError {
     MissingToken,
     InvalidToken,
     MissingUser,
+    Forbidden,
 }
 
 impl std::error::Error for AuthError {}
@@ -73,6 +315,20 @@ impl std::fmt::Display for AuthError {
             Self::MissingToken => write!(f, "Missing token"),
             Self::InvalidToken => write!(f, "Invalid token"),
             Self::MissingUser => write!(f, "Missing user"),
+            Self::Forbidden => write!(f, "Only administrators can do this"),
+        }
+    }
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MissingCredentials => "auth.missing_credentials",
+            Self::InvalidCredentials => "auth.invalid_credentials",
+            Self::MissingToken => "auth.missing_token",
+            Self::InvalidToken => "auth.invalid_token",
+            Self::MissingUser => "auth.missing_user",
+            Self::Forbidden => "auth.forbidden",
         }
     }
 }
@@ -90,8 +346,119 @@ impl IntoResponse for AuthError {
             Self::InvalidCredentials | Self::InvalidToken | Self::MissingUser => {
                 StatusCode::UNAUTHORIZED
             }
+            Self::Forbidden => StatusCode::FORBIDDEN,
         };
 
-        (status, format!("{}", self)).into_response()
+        Problem::new(status, self.code(), &self).into_response()
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    Missing,
+    TooLarge,
+    UnsupportedFormat,
+    Dimensions,
+    Corrupted,
+    Encode,
+}
+
+impl std::error::Error for UploadError {}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "Missing uploaded file"),
+            Self::TooLarge => write!(f, "Uploaded file is too large"),
+            Self::UnsupportedFormat => write!(f, "Unsupported file format"),
+            Self::Dimensions => write!(f, "Image dimensions are too large"),
+            Self::Corrupted => write!(f, "Failed to decode uploaded image"),
+            Self::Encode => write!(f, "Failed to process uploaded image"),
+        }
     }
 }
+
+impl UploadError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Missing => "upload.missing",
+            Self::TooLarge => "upload.too_large",
+            Self::UnsupportedFormat => "upload.unsupported_format",
+            Self::Dimensions => "upload.dimensions",
+            Self::Corrupted => "upload.corrupted",
+            Self::Encode => "upload.encode",
+        }
+    }
+}
+
+impl From<UploadError> for ApiError {
+    fn from(e: UploadError) -> Self {
+        Self::Upload(e)
+    }
+}
+
+impl From<FileError> for ApiError {
+    fn from(e: FileError) -> Self {
+        Self::File(e)
+    }
+}
+
+#[tokio::test]
+async fn test_problem() {
+    use http_body_util::BodyExt;
+
+    let error = ApiError::Settings(SettingsError::Invalid(vec![crate::settings::Invalid {
+        key: String::from("site_name"),
+        message: String::from("Must not be empty"),
+    }]));
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["status"], 422);
+    assert_eq!(problem["code"], "settings.invalid");
+    assert_eq!(problem["errors"][0]["field"], "site_name");
+
+    let body = ApiError::Query(UserError::NotFound)
+        .into_response()
+        .into_body();
+    let body = body.collect().await.unwrap().to_bytes();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["code"], "user.not_found");
+    assert!(problem.get("errors").is_none());
+
+    let response = ApiError::from(AuthError::Forbidden).into_response();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["code"], "auth.forbidden");
+
+    let violation = DieselError::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new(String::from(
+            "duplicate key value violates \"users_login_key\"",
+        )),
+    );
+    let response = ApiError::Database(DatabaseError::Query(violation)).into_response();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["code"], "database.conflict");
+    assert!(!problem["detail"]
+        .as_str()
+        .unwrap()
+        .contains("users_login_key"));
+
+    let failure = DatabaseError::Query(DieselError::BrokenTransactionManager);
+    let response = ApiError::Database(failure).into_response();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["detail"], "Internal server error");
+    assert!(problem["request_id"].is_string());
+}
@@ -0,0 +1,81 @@
+//! Extractors rejecting requests with an [`ApiError`], so clients get the usual error body.
+
+use std::ops::{Deref, DerefMut};
+
+use async_trait::async_trait;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::response::{IntoResponse, Response};
+
+use super::errors::ApiError;
+
+/// [`axum::Json`] rejecting bodies it cannot parse with [`ApiError::Body`].
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// [`axum::extract::Path`] rejecting parameters it cannot parse with [`ApiError::Path`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+/// [`axum::extract::Query`] rejecting query strings it cannot parse with [`ApiError::QueryString`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+/// [`axum::extract::Multipart`] rejecting requests that are not multipart with [`ApiError::Multipart`].
+pub struct Multipart(pub axum::extract::Multipart);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for Multipart {
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Multipart(
+            axum::extract::Multipart::from_request(req, state).await?,
+        ))
+    }
+}
+
+impl Deref for Multipart {
+    type Target = axum::extract::Multipart;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Multipart {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[tokio::test]
+async fn test_rejection() {
+    use axum::http::header;
+
+    let (mut parts, _) = axum::http::Request::get("/api/user/all?limit=x")
+        .body(())
+        .unwrap()
+        .into_parts();
+    let Err(rejection) =
+        Query::<crate::repository::Page>::from_request_parts(&mut parts, &()).await
+    else {
+        panic!("query string should be rejected");
+    };
+    assert_eq!(rejection.code(), "request.invalid_query");
+
+    let response = rejection.into_response();
+    assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+}
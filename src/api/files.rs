@@ -0,0 +1,478 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{response::IntoResponse, Extension};
+use std::sync::Arc;
+
+use crate::state::AppState;
+use crate::storage::blobs;
+use crate::{
+    db::file::{File, Visibility},
+    db::schema::files,
+    db::{self, types::Id, Isolation},
+    repository::Read,
+};
+
+use super::errors::{ApiError, Problem, UploadError};
+use super::extract::{Json, Multipart, Path};
+use super::user::{upload_error, UserError};
+
+#[derive(Debug, PartialEq)]
+pub enum FileError {
+    NotFound,
+    QuotaExceeded,
+    InvalidMetadata,
+    OffsetMismatch,
+    UnsupportedVersion,
+    UnsupportedContentType,
+}
+
+impl std::error::Error for FileError {}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "File not found"),
+            Self::QuotaExceeded => write!(f, "Storage quota exceeded"),
+            Self::InvalidMetadata => write!(f, "Invalid upload metadata"),
+            Self::OffsetMismatch => write!(f, "Upload offset does not match"),
+            Self::UnsupportedVersion => write!(f, "Unsupported tus protocol version"),
+            Self::UnsupportedContentType => write!(f, "Unsupported upload content type"),
+        }
+    }
+}
+
+impl FileError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "file.not_found",
+            Self::QuotaExceeded => "file.quota_exceeded",
+            Self::InvalidMetadata => "file.invalid_metadata",
+            Self::OffsetMismatch => "file.offset_mismatch",
+            Self::UnsupportedVersion => "file.unsupported_version",
+            Self::UnsupportedContentType => "file.unsupported_content_type",
+        }
+    }
+}
+
+pub mod schema {
+    use crate::db::file::{self, Visibility};
+
+    #[derive(serde::Serialize, utoipa::ToSchema)]
+    pub struct File {
+        pub id: String,
+        pub owner_id: String,
+        pub name: String,
+        pub mime: String,
+        pub size: i64,
+        pub visibility: Visibility,
+        /// Whether the upload has finished and the file can be downloaded.
+        pub complete: bool,
+        pub created_at: String,
+        pub updated_at: String,
+        pub url: String,
+    }
+
+    impl File {
+        pub fn from(file: &file::File) -> Self {
+            File {
+                id: file.id.to_string(),
+                owner_id: file.owner_id.to_string(),
+                name: file.name.to_owned(),
+                mime: file.mime.to_owned(),
+                size: file.size,
+                visibility: file.visibility(),
+                complete: file.is_complete(),
+                created_at: file.created_at.to_rfc3339(),
+                updated_at: file.updated_at.to_rfc3339(),
+                url: format!("/resources/files/{}", file.id),
+            }
+        }
+    }
+
+    #[derive(serde::Deserialize, utoipa::ToSchema)]
+    pub struct SetVisibility {
+        pub visibility: Visibility,
+    }
+
+    #[derive(utoipa::ToSchema)]
+    pub struct Upload {
+        #[schema(value_type = String, format = Binary)]
+        pub file: Vec<u8>,
+        pub visibility: Option<Visibility>,
+    }
+}
+
+#[utoipa::path(get, path = "/api/files",
+    security(("token" = [])),
+    responses((status = 200, body = [File]), (status = "4XX", response = Problem))
+)]
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+) -> Result<Json<Vec<schema::File>>, ApiError> {
+    use diesel::prelude::*;
+
+    let uuid = user_id.ok_or(ApiError::Query(UserError::Unauthorized))?;
+
+    let files = db::execute(&state.database, move |conn| {
+        files::table
+            .filter(files::owner_id.eq(Id(uuid)))
+            .order(files::created_at.desc())
+            .select(File::as_select())
+            .get_results(conn)
+    })
+    .await?;
+
+    Ok(Json(files.iter().map(schema::File::from).collect()))
+}
+
+#[utoipa::path(get, path = "/api/files/user/{login}",
+    params(("login", Path,)),
+    responses((status = 200, body = [File]), (status = 404, response = Problem))
+)]
+pub async fn public(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+) -> Result<Json<Vec<schema::File>>, ApiError> {
+    use diesel::prelude::*;
+
+    let owner = state
+        .users
+        .find_by_login(&login, Read::Replica(None))
+        .await?
+        .ok_or(ApiError::Query(UserError::NotFound))?;
+
+    let files = db::execute(&state.database, move |conn| {
+        files::table
+            .filter(files::owner_id.eq(Id(owner.id)))
+            .filter(files::visibility.eq(Visibility::Public.as_str()))
+            .filter(files::hash.is_not_null())
+            .order(files::created_at.desc())
+            .select(File::as_select())
+            .get_results(conn)
+    })
+    .await?;
+
+    Ok(Json(files.iter().map(schema::File::from).collect()))
+}
+
+#[utoipa::path(get, path = "/api/files/{id}",
+    params(("id", Path,)),
+    responses((status = 200, body = File), (status = 404, response = Problem))
+)]
+pub async fn info(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+) -> Result<Json<schema::File>, ApiError> {
+    let file = find(&state, file_id).await?;
+
+    if !file.is_visible_to(user_id) {
+        return Err(ApiError::File(FileError::NotFound));
+    }
+
+    Ok(Json(schema::File::from(&file)))
+}
+
+#[utoipa::path(post, path = "/api/files",
+    security(("token" = [])),
+    request_body(content = Upload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, body = File),
+        (status = "4XX", response = Problem),
+        (status = 500, response = Problem)
+    )
+)]
+pub async fn upload(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    mut multipart: Multipart,
+) -> Result<Json<schema::File>, ApiError> {
+    let uuid = user_id.ok_or(ApiError::Query(UserError::Unauthorized))?;
+
+    let max_size = state.settings().file_max_size;
+    let mut upload: Option<(String, Vec<u8>)> = None;
+    let mut visibility = Visibility::Private;
+
+    while let Some(mut field) = multipart.next_field().await.map_err(upload_error)? {
+        match field.name() {
+            Some("file") => {
+                let name = field.file_name().unwrap_or("file").to_string();
+                let mut content = Vec::new();
+
+                while let Some(chunk) = field.chunk().await.map_err(upload_error)? {
+                    if content.len() + chunk.len() > max_size {
+                        return Err(UploadError::TooLarge.into());
+                    }
+                    content.extend_from_slice(&chunk);
+                }
+
+                upload = Some((name, content));
+            }
+            Some("visibility") => {
+                visibility = field
+                    .text()
+                    .await
+                    .map_err(upload_error)?
+                    .parse()
+                    .map_err(|_| ApiError::File(FileError::InvalidMetadata))?;
+            }
+            _ => {}
+        }
+    }
+
+    let (name, content) = upload.ok_or(UploadError::Missing)?;
+    let file = create(&state, uuid, name, content.len() as i64, visibility).await?;
+
+    match complete(&state, file.id, Content::Memory(content)).await {
+        Ok(file) => Ok(Json(schema::File::from(&file))),
+        Err(e) => {
+            delete(&state, file).await?;
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(post, path = "/api/files/{id}/visibility",
+    security(("token" = [])),
+    params(("id", Path,)),
+    request_body = SetVisibility,
+    responses((status = 200, body = File), (status = 404, response = Problem))
+)]
+pub async fn visibility(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+    Json(body): Json<schema::SetVisibility>,
+) -> Result<Json<schema::File>, ApiError> {
+    use diesel::prelude::*;
+
+    let file = owned(&state, user_id, file_id).await?;
+
+    let file = db::execute(&state.database, move |conn| {
+        diesel::update(files::table.find(Id(file.id)))
+            .set(files::visibility.eq(body.visibility.as_str()))
+            .returning(files::all_columns)
+            .get_result(conn)
+    })
+    .await?;
+
+    Ok(Json(schema::File::from(&file)))
+}
+
+#[utoipa::path(delete, path = "/api/files/{id}",
+    security(("token" = [])),
+    params(("id", Path,)),
+    responses((status = 200), (status = 404, response = Problem))
+)]
+pub async fn remove(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let file = owned(&state, user_id, file_id).await?;
+
+    delete(&state, file).await?;
+
+    Ok(StatusCode::OK)
+}
+
+pub(super) async fn find(state: &AppState, file_id: uuid::Uuid) -> Result<File, ApiError> {
+    use diesel::prelude::*;
+
+    db::execute(&state.database, move |conn| {
+        files::table
+            .find(Id(file_id))
+            .select(File::as_select())
+            .first(conn)
+            .optional()
+    })
+    .await?
+    .ok_or(ApiError::File(FileError::NotFound))
+}
+
+/// Finds a file of the current user; files of others are reported as missing.
+pub(super) async fn owned(
+    state: &AppState,
+    user_id: Option<uuid::Uuid>,
+    file_id: uuid::Uuid,
+) -> Result<File, ApiError> {
+    let uuid = user_id.ok_or(ApiError::Query(UserError::Unauthorized))?;
+    let file = find(state, file_id).await?;
+
+    match file.owner_id == uuid {
+        true => Ok(file),
+        false => Err(ApiError::File(FileError::NotFound)),
+    }
+}
+
+/// Registers a new upload, reserving its size in the owner's quota.
+pub(super) async fn create(
+    state: &AppState,
+    owner_id: uuid::Uuid,
+    name: String,
+    size: i64,
+    visibility: Visibility,
+) -> Result<File, ApiError> {
+    use diesel::prelude::*;
+
+    let settings = state.settings();
+    if size < 0 || size as u64 > settings.file_max_size as u64 {
+        return Err(UploadError::TooLarge.into());
+    }
+
+    let quota = settings.user_quota as i64;
+    let mime = mime_guess::from_path(&name)
+        .first_or_octet_stream()
+        .to_string();
+
+    // Serializable, so that concurrent uploads cannot together exceed the quota.
+    db::transaction(&state.database, Isolation::Serializable, move |conn| {
+        if db::file::used_space(conn, owner_id)? + size > quota {
+            return Ok(None);
+        }
+
+        diesel::insert_into(files::table)
+            .values((
+                files::owner_id.eq(Id(owner_id)),
+                files::name.eq(&name),
+                files::mime.eq(&mime),
+                files::size.eq(size),
+                files::visibility.eq(visibility.as_str()),
+            ))
+            .returning(files::all_columns)
+            .get_result(conn)
+            .map(Some)
+    })
+    .await?
+    .ok_or(ApiError::File(FileError::QuotaExceeded))
+}
+
+/// Content of a finished upload.
+pub(super) enum Content {
+    Memory(Vec<u8>),
+    /// A local file, read in chunks so large uploads are never held in memory.
+    File(std::path::PathBuf),
+}
+
+/// Stores the received content as a blob and marks the upload as complete.
+///
+/// The MIME type is sniffed from the content, the name only serves as a fallback
+/// for formats without magic bytes.
+pub(super) async fn complete(
+    state: &AppState,
+    file_id: uuid::Uuid,
+    content: Content,
+) -> Result<File, ApiError> {
+    use diesel::prelude::*;
+
+    let (hash, size, sniffed) = match &content {
+        Content::Memory(content) => (
+            blobs::hash(content),
+            content.len() as i64,
+            infer::get(content).map(|kind| kind.mime_type().to_string()),
+        ),
+        Content::File(path) => {
+            let path = path.to_owned();
+            tokio::task::spawn_blocking(move || {
+                Ok::<_, std::io::Error>((
+                    blobs::hash_file(&path)?,
+                    std::fs::metadata(&path)?.len() as i64,
+                    infer::get_from_path(&path)?.map(|kind| kind.mime_type().to_string()),
+                ))
+            })
+            .await
+            .map_err(|_| UploadError::Encode)?
+            .map_err(|_| UploadError::Encode)?
+        }
+    };
+
+    let blob = hash.clone();
+    let acquired = db::execute(&state.database, move |conn| {
+        db::blob::acquire(conn, &blob, size)
+    })
+    .await?;
+
+    let key = blobs::data_key(&hash);
+    let exists = match acquired.ref_count > 1 {
+        true => state.storage.exists(&key).await,
+        false => Ok(false),
+    };
+    let stored = match exists {
+        Ok(true) => Ok(()),
+        Ok(false) => match content {
+            Content::Memory(content) => state.storage.put(&key, content).await,
+            Content::File(path) => state.storage.put_file(&key, &path).await,
+        },
+        Err(e) => Err(e),
+    };
+
+    let blob = hash.clone();
+    let result = match stored {
+        Ok(()) => db::transaction(&state.database, Isolation::ReadCommitted, move |conn| {
+            let file = diesel::update(files::table.find(Id(file_id)))
+                .set((
+                    files::hash.eq(Some(&blob)),
+                    files::upload_offset.eq(size),
+                    files::size.eq(size),
+                ))
+                .returning(files::all_columns)
+                .get_result::<File>(conn)?;
+
+            match &sniffed {
+                Some(mime) => diesel::update(files::table.find(Id(file.id)))
+                    .set(files::mime.eq(mime))
+                    .returning(files::all_columns)
+                    .get_result(conn),
+                None => Ok(file),
+            }
+        })
+        .await
+        .map_err(ApiError::from),
+        Err(e) => Err(e.into()),
+    };
+
+    if result.is_err() {
+        blobs::release(state, hash).await;
+    }
+
+    result
+}
+
+/// Removes the file record, its partial upload and the blob reference.
+pub(super) async fn delete(state: &AppState, file: File) -> Result<(), ApiError> {
+    use diesel::prelude::*;
+
+    let (file_id, hash) = (file.id, file.hash);
+    db::execute(&state.database, move |conn| {
+        diesel::delete(files::table.find(Id(file_id))).execute(conn)
+    })
+    .await?;
+
+    if let Ok(path) = partial_path(state, file_id) {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+
+    if let Some(hash) = hash {
+        blobs::release(state, hash).await;
+    }
+
+    Ok(())
+}
+
+/// Local file holding the chunks of an unfinished resumable upload.
+///
+/// Object stores have no append operation, so chunks are collected on disk
+/// and moved into the storage once the upload is complete.
+pub(super) fn partial_path(
+    state: &AppState,
+    file_id: uuid::Uuid,
+) -> Result<std::path::PathBuf, ApiError> {
+    Ok(state
+        .config
+        .load()
+        .paths
+        .partial_uploads()
+        .map_err(|_| UploadError::Encode)?
+        .join(format!("{}.part", file_id)))
+}
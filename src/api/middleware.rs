@@ -3,29 +3,69 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::{Request, State},
-    http::{header, StatusCode},
+    http::{header, HeaderName, HeaderValue, StatusCode},
     middleware::Next,
     response::IntoResponse,
-    Json,
 };
 use axum_extra::extract::CookieJar;
+use tower_http::cors::AllowOrigin;
 
-use crate::{
-    db::{self, schema::users, user::User},
-    state::AppState,
-};
+use crate::db::user::User;
+use crate::repository::Read;
+use crate::state::AppState;
 
 use super::errors::AuthError;
 use super::{errors::ApiError, token::TokenClaims};
 
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest id accepted from a client or proxy, longer ones are replaced.
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if it went through [`request_id`].
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.to_owned()).ok()
+}
+
+/// Names every request with the `x-request-id` it came with or a new one,
+/// for error responses and logs to refer to, and sends it back in the response.
+pub async fn request_id(mut req: Request<Body>, next: Next) -> impl IntoResponse {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        .map(|id| id.to_owned())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let value = HeaderValue::from_str(&id).expect("request id is a valid header value");
+    req.headers_mut()
+        .insert(X_REQUEST_ID.to_owned(), value.to_owned());
+
+    let mut response = REQUEST_ID.scope(id, next.run(req)).await;
+    response
+        .headers_mut()
+        .insert(X_REQUEST_ID.to_owned(), value);
+
+    response
+}
+
 pub async fn jwt(
     cookie_jar: CookieJar,
     State(state): State<Arc<AppState>>,
     mut req: Request<Body>,
     next: Next,
 ) -> Result<impl IntoResponse, ApiError> {
-    use diesel::prelude::*;
-
     let token = cookie_jar
         .get("token")
         .map(|cookie| cookie.value().to_string())
@@ -38,24 +78,40 @@ pub async fn jwt(
         });
 
     let token = token.ok_or(AuthError::MissingToken)?;
-    let claims = TokenClaims::validate(token, state.config.jwt.secret.to_owned())
+    let claims = TokenClaims::validate(token, state.config.load().jwt.secret.to_owned())
         .map_err(|_| AuthError::InvalidToken)?;
 
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| AuthError::InvalidToken)?;
 
-    let user = db::execute(&state.database, move |conn| {
-        users::table
-            .into_boxed()
-            .filter(users::id.eq(user_id))
-            .first::<User>(conn)
-            .optional()
-    })
-    .await?;
+    let user = state
+        .users
+        .find(user_id, Read::Primary)
+        .await?
+        .ok_or(AuthError::MissingUser)?;
 
-    let user = user.ok_or(AuthError::MissingUser)?;
+    if state.mark_seen(user.id) {
+        state.record_seen(user.id);
+    }
+    let changes = !req.method().is_safe();
 
     req.extensions_mut().insert(user);
-    Ok(next.run(req).await)
+    let response = next.run(req).await;
+
+    // Later lookups of the user go to the primary until the replicas have the changes;
+    // failed requests changed nothing.
+    if changes && response.status().is_success() {
+        state.database.replicas.record_write(user_id);
+    }
+
+    Ok(response)
+}
+
+/// Rejects users other than administrators, for handlers behind [`jwt`].
+pub fn admin(user: &User) -> Result<(), AuthError> {
+    match user.is_admin {
+        true => Ok(()),
+        false => Err(AuthError::Forbidden),
+    }
 }
 
 pub async fn jwt_auth(
@@ -76,9 +132,55 @@ pub async fn jwt_auth(
         });
 
     let user_id = token
-        .and_then(|token| TokenClaims::validate(token, state.config.jwt.secret.to_owned()).ok())
+        .and_then(|token| {
+            TokenClaims::validate(token, state.config.load().jwt.secret.to_owned()).ok()
+        })
         .and_then(|claims| uuid::Uuid::parse_str(&claims.sub).ok());
 
+    // Tokens outlive deleted users, so only users still there are recorded.
+    if let Some(user_id) = user_id.filter(|id| state.mark_seen(*id)) {
+        if let Ok(Some(_)) = state.users.find(user_id, Read::Primary).await {
+            state.record_seen(user_id);
+        }
+    }
+
+    let changes = !req.method().is_safe();
+
     req.extensions_mut().insert(user_id);
-    Ok(next.run(req).await)
+    let response = next.run(req).await;
+
+    if let Some(user_id) = user_id.filter(|_| changes && response.status().is_success()) {
+        state.database.replicas.record_write(user_id);
+    }
+
+    Ok(response)
+}
+
+/// Reads a size limit from the current configuration or settings.
+pub type Limit = fn(&AppState) -> usize;
+
+/// Limits request bodies to the size `limit` reads from the application state,
+/// so the limits follow configuration reloads and settings changes. Use with `DefaultBodyLimit::disable()`.
+pub async fn body_limit(
+    State((state, limit)): State<(Arc<AppState>, Limit)>,
+    req: Request<Body>,
+    next: Next,
+) -> impl IntoResponse {
+    let limit = limit(&state);
+
+    next.run(req.map(|body| Body::new(http_body_util::Limited::new(body, limit))))
+        .await
+}
+
+/// Allows the origins listed in the current configuration.
+pub fn allow_origin(state: Arc<AppState>) -> AllowOrigin {
+    AllowOrigin::predicate(move |origin, _| {
+        state
+            .config
+            .load()
+            .server
+            .cors_origins
+            .iter()
+            .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+    })
 }
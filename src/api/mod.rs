@@ -1,7 +1,12 @@
+pub mod admin;
 pub mod doc;
 pub mod errors;
+pub mod extract;
+pub mod files;
 pub mod middleware;
+pub mod settings;
 pub mod token;
+pub mod tus;
 pub mod user;
 
 use std::sync::Arc;
@@ -10,7 +15,7 @@ use axum::{
     extract::DefaultBodyLimit,
     http::{header::*, Method, StatusCode},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{get, options, post},
     Json, Router,
 };
 use serde_json::json;
@@ -18,17 +23,50 @@ use tower_http::cors::CorsLayer;
 
 use crate::state::AppState;
 
+/// Room for multipart boundaries and part headers on top of the file size limit.
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
 pub fn routes(state: Arc<AppState>) -> Router {
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers(vec![ORIGIN, AUTHORIZATION, ACCEPT, CONTENT_TYPE, COOKIE])
-        .allow_origin([
-            "http://localhost:54600".parse().unwrap(),
-            "http://localhost:5173".parse().unwrap(),
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::OPTIONS,
+            Method::HEAD,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(vec![
+            ORIGIN,
+            AUTHORIZATION,
+            ACCEPT,
+            CONTENT_TYPE,
+            COOKIE,
+            tus::TUS_RESUMABLE,
+            tus::UPLOAD_OFFSET,
+            tus::UPLOAD_LENGTH,
+            tus::UPLOAD_METADATA,
+        ])
+        .expose_headers(vec![
+            LOCATION,
+            tus::TUS_RESUMABLE,
+            tus::TUS_VERSION,
+            tus::TUS_EXTENSION,
+            tus::TUS_MAX_SIZE,
+            tus::UPLOAD_OFFSET,
+            tus::UPLOAD_LENGTH,
         ])
+        .allow_origin(middleware::allow_origin(state.to_owned()))
         .allow_credentials(true);
 
     let jwt = axum::middleware::from_fn_with_state(state.to_owned(), middleware::jwt_auth);
+    let admin = axum::middleware::from_fn_with_state(state.to_owned(), middleware::jwt);
+    let body_limit = |limit: middleware::Limit| {
+        (
+            DefaultBodyLimit::disable(),
+            axum::middleware::from_fn_with_state((state.to_owned(), limit), middleware::body_limit),
+        )
+    };
 
     Router::new()
         .route("/healthcheck", get(healthcheck))
@@ -45,11 +83,65 @@ pub fn routes(state: Arc<AppState>) -> Router {
             "/user/:login",
             get(user::profile).route_layer(jwt.to_owned()),
         )
+        .route(
+            "/user/avatar/style",
+            post(user::avatar_style).route_layer(jwt.to_owned()),
+        )
         .route(
             "/user/avatar",
             post(user::avatar)
-                .route_layer(jwt)
-                .layer(DefaultBodyLimit::max(10 * 10000)),
+                .route_layer(jwt.to_owned())
+                .layer(body_limit(|state| {
+                    state.settings().avatar_max_size + MULTIPART_OVERHEAD
+                })),
+        )
+        .route(
+            "/files",
+            get(files::list)
+                .post(files::upload)
+                .route_layer(jwt.to_owned())
+                .layer(body_limit(|state| {
+                    state.settings().file_max_size + MULTIPART_OVERHEAD
+                })),
+        )
+        .route(
+            "/files/uploads",
+            options(tus::options)
+                .post(tus::create)
+                .route_layer(jwt.to_owned()),
+        )
+        .route(
+            "/files/uploads/:id",
+            axum::routing::head(tus::status)
+                .patch(tus::append)
+                .delete(tus::terminate)
+                .route_layer(jwt.to_owned())
+                .layer(body_limit(|state| {
+                    state.config.load().uploads.chunk_max_size
+                })),
+        )
+        .route("/files/user/:login", get(files::public))
+        .route("/settings", get(settings::public))
+        .route(
+            "/admin/settings",
+            get(settings::get)
+                .patch(settings::update)
+                .route_layer(admin.to_owned()),
+        )
+        .route(
+            "/admin/settings/history",
+            get(settings::history).route_layer(admin.to_owned()),
+        )
+        .route("/admin/database", get(admin::database).route_layer(admin))
+        .route(
+            "/files/:id",
+            get(files::info)
+                .delete(files::remove)
+                .route_layer(jwt.to_owned()),
+        )
+        .route(
+            "/files/:id/visibility",
+            post(files::visibility).route_layer(jwt),
         )
         .layer(cors)
         .fallback(fallback)
@@ -67,10 +159,5 @@ pub async fn healthcheck() -> impl IntoResponse {
 }
 
 pub async fn fallback() -> impl IntoResponse {
-    (
-        StatusCode::NOT_FOUND,
-        Json(json!({
-            "status": StatusCode::NOT_FOUND.to_string(),
-        })),
-    )
+    errors::Problem::new(StatusCode::NOT_FOUND, "route.not_found", "No such endpoint")
 }
@@ -0,0 +1,185 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::Extension;
+use serde_json::Value;
+
+use crate::db::{self, user::User, Isolation};
+use crate::settings::{self, Invalid, Settings};
+use crate::state::AppState;
+
+use super::errors::{ApiError, Problem};
+use super::extract::Json;
+use super::middleware::admin;
+
+/// Number of entries returned by the settings history.
+const HISTORY_LIMIT: i64 = 100;
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Invalid(Vec<Invalid>),
+}
+
+impl std::error::Error for SettingsError {}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(problems) => {
+                write!(f, "Invalid settings: ")?;
+                for (n, problem) in problems.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}: {}", problem.key, problem.message)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl SettingsError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Invalid(_) => "settings.invalid",
+        }
+    }
+}
+
+impl From<SettingsError> for ApiError {
+    fn from(e: SettingsError) -> Self {
+        Self::Settings(e)
+    }
+}
+
+pub mod schema {
+    use crate::config::Registration;
+    use crate::db::setting;
+
+    /// Settings everyone may read.
+    #[derive(serde::Serialize, utoipa::ToSchema)]
+    pub struct Public {
+        pub site_name: String,
+        pub registration: Registration,
+        pub default_locale: String,
+        pub announcement: Option<String>,
+    }
+
+    #[derive(serde::Serialize, utoipa::ToSchema)]
+    pub struct Change {
+        pub key: String,
+        /// Previous value, `null` when the default was in effect.
+        #[schema(value_type = Object)]
+        pub old_value: Option<serde_json::Value>,
+        /// New value, `null` when the default was restored.
+        #[schema(value_type = Object)]
+        pub new_value: Option<serde_json::Value>,
+        pub changed_by: Option<String>,
+        pub changed_at: String,
+    }
+
+    impl Change {
+        pub fn from(change: &setting::Change) -> Self {
+            let parse = |value: &Option<String>| {
+                value
+                    .as_deref()
+                    .and_then(|value| serde_json::from_str(value).ok())
+            };
+
+            Change {
+                key: change.key.to_owned(),
+                old_value: parse(&change.old_value),
+                new_value: parse(&change.new_value),
+                changed_by: change.changed_by.map(|id| id.to_string()),
+                changed_at: change.changed_at.to_rfc3339(),
+            }
+        }
+    }
+}
+
+#[utoipa::path(get, path = "/api/settings", responses((status = 200, body = Public)))]
+pub async fn public(State(state): State<Arc<AppState>>) -> Json<schema::Public> {
+    let settings = state.settings();
+
+    Json(schema::Public {
+        site_name: settings.site_name,
+        registration: settings.registration,
+        default_locale: settings.default_locale,
+        announcement: settings.announcement,
+    })
+}
+
+#[utoipa::path(get, path = "/api/admin/settings",
+    security(("token" = [])),
+    responses((status = 200, body = Settings), (status = 403, response = Problem))
+)]
+pub async fn get(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Settings>, ApiError> {
+    admin(&user)?;
+
+    Ok(Json(state.settings()))
+}
+
+#[utoipa::path(patch, path = "/api/admin/settings",
+    security(("token" = [])),
+    request_body(content = Object, description = "Settings to change, `null` restores the default"),
+    responses((status = 200, body = Settings), (status = "4XX", response = Problem))
+)]
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(changes): Json<serde_json::Map<String, Value>>,
+) -> Result<Json<Settings>, ApiError> {
+    admin(&user)?;
+
+    let config = state.config.load();
+    let stored = state.settings.load_full();
+    let settings = Settings::update(&config, &stored, &changes).map_err(SettingsError::Invalid)?;
+
+    let changed = settings.changed(&config);
+    let writes = stored
+        .keys()
+        .chain(changed.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|key| stored.get(*key) != changed.get(*key))
+        .map(|key| (key.to_owned(), changed.get(key).map(Value::to_string)))
+        .collect::<Vec<_>>();
+
+    let user_id = user.id;
+    db::transaction(&state.database, Isolation::ReadCommitted, move |conn| {
+        writes.iter().try_for_each(|(key, value)| {
+            db::setting::set(conn, key, value.to_owned(), Some(user_id))
+        })
+    })
+    .await?;
+
+    state
+        .settings
+        .store(Arc::new(settings::load(&state.database).await?));
+
+    Ok(Json(state.settings()))
+}
+
+#[utoipa::path(get, path = "/api/admin/settings/history",
+    security(("token" = [])),
+    responses((status = 200, body = [Change]), (status = 403, response = Problem))
+)]
+pub async fn history(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<schema::Change>>, ApiError> {
+    admin(&user)?;
+
+    let changes = db::execute(&state.database, |conn| {
+        db::setting::history(conn, HISTORY_LIMIT)
+    })
+    .await?;
+
+    Ok(Json(changes.iter().map(schema::Change::from).collect()))
+}
@@ -0,0 +1,341 @@
+//! Resumable uploads implementing the core of the [tus 1.0.0](https://tus.io/protocols/resumable-upload)
+//! protocol with the `creation` and `termination` extensions.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use base64::Engine;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::db::file::Visibility;
+use crate::state::AppState;
+
+use super::errors::{ApiError, Problem, UploadError};
+use super::extract::Path;
+use super::files::{self, FileError};
+use super::user::UserError;
+
+pub const VERSION: &str = "1.0.0";
+pub const EXTENSIONS: &str = "creation,termination";
+
+pub const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+pub const TUS_VERSION: HeaderName = HeaderName::from_static("tus-version");
+pub const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+pub const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+pub const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+pub const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+pub const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// Uploads a chunk is being appended to by this process.
+#[derive(Default)]
+pub struct Appending(Mutex<HashSet<uuid::Uuid>>);
+
+/// Exclusive right to append to an upload, released when dropped.
+pub struct Claim<'a> {
+    appending: &'a Appending,
+    id: uuid::Uuid,
+}
+
+impl Appending {
+    /// Claims the upload, or `None` while another request is appending to it.
+    pub fn claim(&self, id: uuid::Uuid) -> Option<Claim<'_>> {
+        let mut ids = self.0.lock().unwrap_or_else(|e| e.into_inner());
+
+        ids.insert(id).then(|| Claim {
+            appending: self,
+            id,
+        })
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        let mut ids = self.appending.0.lock().unwrap_or_else(|e| e.into_inner());
+        ids.remove(&self.id);
+    }
+}
+
+#[utoipa::path(options, path = "/api/files/uploads",
+    responses((status = 204, description = "Supported tus version, extensions and maximum size"))
+)]
+pub async fn options(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_RESUMABLE, HeaderValue::from_static(VERSION)),
+            (TUS_VERSION, HeaderValue::from_static(VERSION)),
+            (TUS_EXTENSION, HeaderValue::from_static(EXTENSIONS)),
+            (
+                TUS_MAX_SIZE,
+                HeaderValue::from(state.settings().file_max_size),
+            ),
+        ],
+    )
+}
+
+#[utoipa::path(post, path = "/api/files/uploads",
+    security(("token" = [])),
+    params(
+        ("Upload-Length" = u64, Header,),
+        ("Upload-Metadata" = Option<String>, Header, description = "`filename` and `visibility`"),
+    ),
+    responses((status = 201), (status = "4XX", response = Problem))
+)]
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    check_version(&headers)?;
+    let uuid = user_id.ok_or(ApiError::Query(UserError::Unauthorized))?;
+
+    let length =
+        header_u64(&headers, &UPLOAD_LENGTH).ok_or(ApiError::File(FileError::InvalidMetadata))?;
+    let metadata = headers
+        .get(&UPLOAD_METADATA)
+        .map(|value| value.to_str().map_err(|_| FileError::InvalidMetadata))
+        .transpose()?
+        .map(parse_metadata)
+        .transpose()?
+        .unwrap_or_default();
+
+    let name = metadata
+        .iter()
+        .find(|(key, _)| key == "filename")
+        .map(|(_, value)| value.to_owned())
+        .unwrap_or_else(|| String::from("file"));
+    let visibility = match metadata.iter().find(|(key, _)| key == "visibility") {
+        Some((_, value)) => value
+            .parse()
+            .map_err(|_| ApiError::File(FileError::InvalidMetadata))?,
+        None => Visibility::Private,
+    };
+
+    let file = files::create(&state, uuid, name, length as i64, visibility).await?;
+
+    if length == 0 {
+        files::complete(&state, file.id, files::Content::Memory(Vec::new())).await?;
+    } else {
+        let path = files::partial_path(&state, file.id)?;
+        let created = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::File::create(&path).await.map(|_| ())
+        };
+
+        if created.await.is_err() {
+            files::delete(&state, file).await?;
+            return Err(UploadError::Encode.into());
+        }
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        [
+            (TUS_RESUMABLE, HeaderValue::from_static(VERSION)),
+            (
+                header::LOCATION,
+                HeaderValue::from_str(&format!("/api/files/uploads/{}", file.id))
+                    .map_err(|_| UploadError::Encode)?,
+            ),
+        ],
+    )
+        .into_response())
+}
+
+#[utoipa::path(head, path = "/api/files/uploads/{id}",
+    security(("token" = [])),
+    params(("id", Path,)),
+    responses((status = 200, description = "Current `Upload-Offset`"), (status = 404, response = Problem))
+)]
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    check_version(&headers)?;
+    let file = files::owned(&state, user_id, file_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            (TUS_RESUMABLE, HeaderValue::from_static(VERSION)),
+            (UPLOAD_OFFSET, HeaderValue::from(file.upload_offset)),
+            (UPLOAD_LENGTH, HeaderValue::from(file.size)),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+        ],
+    )
+        .into_response())
+}
+
+#[utoipa::path(patch, path = "/api/files/uploads/{id}",
+    security(("token" = [])),
+    params(("id", Path,), ("Upload-Offset" = u64, Header,)),
+    request_body(content = Vec<u8>, content_type = "application/offset+octet-stream"),
+    responses((status = 204, description = "New `Upload-Offset`"), (status = "4XX", response = Problem))
+)]
+pub async fn append(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    use diesel::prelude::*;
+
+    use crate::db::{self, schema::files as table, types::Id};
+
+    check_version(&headers)?;
+    if !matches!(headers.get(header::CONTENT_TYPE), Some(value) if value == OFFSET_OCTET_STREAM) {
+        return Err(ApiError::File(FileError::UnsupportedContentType));
+    }
+
+    // Held until the new offset is stored: concurrent requests at the same offset
+    // would otherwise truncate and overwrite each other's chunk.
+    let _claim = state
+        .appending
+        .claim(file_id)
+        .ok_or(ApiError::File(FileError::OffsetMismatch))?;
+
+    let file = files::owned(&state, user_id, file_id).await?;
+    let offset =
+        header_u64(&headers, &UPLOAD_OFFSET).ok_or(ApiError::File(FileError::InvalidMetadata))?;
+
+    if file.is_complete() || offset != file.upload_offset as u64 {
+        return Err(ApiError::File(FileError::OffsetMismatch));
+    }
+    if offset + body.len() as u64 > file.size as u64 {
+        return Err(UploadError::TooLarge.into());
+    }
+
+    let path = files::partial_path(&state, file.id)?;
+    let written = async {
+        let mut part = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .await?;
+        // Drop whatever a previously interrupted chunk left after the confirmed offset.
+        part.set_len(offset).await?;
+        part.seek(std::io::SeekFrom::End(0)).await?;
+        part.write_all(&body).await?;
+        part.sync_data().await
+    };
+    written.await.map_err(|_| UploadError::Encode)?;
+
+    let new_offset = (offset + body.len() as u64) as i64;
+    let updated = db::execute(&state.database, move |conn| {
+        diesel::update(table::table.find(Id(file_id)))
+            .filter(table::upload_offset.eq(offset as i64))
+            .set(table::upload_offset.eq(new_offset))
+            .execute(conn)
+    })
+    .await?;
+
+    if updated == 0 {
+        return Err(ApiError::File(FileError::OffsetMismatch));
+    }
+
+    if new_offset == file.size {
+        files::complete(&state, file.id, files::Content::File(path.to_owned())).await?;
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    Ok((
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_RESUMABLE, HeaderValue::from_static(VERSION)),
+            (UPLOAD_OFFSET, HeaderValue::from(new_offset)),
+        ],
+    )
+        .into_response())
+}
+
+#[utoipa::path(delete, path = "/api/files/uploads/{id}",
+    security(("token" = [])),
+    params(("id", Path,)),
+    responses((status = 204), (status = 404, response = Problem))
+)]
+pub async fn terminate(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    check_version(&headers)?;
+    let file = files::owned(&state, user_id, file_id).await?;
+
+    files::delete(&state, file).await?;
+
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(TUS_RESUMABLE, HeaderValue::from_static(VERSION))],
+    )
+        .into_response())
+}
+
+fn check_version(headers: &HeaderMap) -> Result<(), FileError> {
+    match headers.get(&TUS_RESUMABLE) {
+        Some(version) if version == VERSION => Ok(()),
+        _ => Err(FileError::UnsupportedVersion),
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Parses `Upload-Metadata`: comma separated pairs of a key and a base64 encoded value.
+fn parse_metadata(metadata: &str) -> Result<Vec<(String, String)>, FileError> {
+    metadata
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+            let value = base64::engine::general_purpose::STANDARD
+                .decode(value.trim())
+                .ok()
+                .and_then(|value| String::from_utf8(value).ok())
+                .ok_or(FileError::InvalidMetadata)?;
+
+            Ok((key.to_string(), value))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_metadata() {
+    assert_eq!(
+        parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential"),
+        Ok(vec![
+            (
+                String::from("filename"),
+                String::from("world_domination_plan.pdf")
+            ),
+            (String::from("is_confidential"), String::new()),
+        ])
+    );
+    assert!(parse_metadata("filename !!!").is_err());
+}
+
+#[test]
+fn test_appending() {
+    let appending = Appending::default();
+    let id = uuid::Uuid::new_v4();
+
+    let claim = appending.claim(id);
+    assert!(claim.is_some());
+    assert!(appending.claim(id).is_none());
+    assert!(appending.claim(uuid::Uuid::new_v4()).is_some());
+
+    drop(claim);
+    assert!(appending.claim(id).is_some());
+}
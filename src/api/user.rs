@@ -1,7 +1,7 @@
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use argon2::{PasswordHash, PasswordVerifier};
 use axum::body::Bytes;
-use axum::extract::{Multipart, Path};
+use axum::extract::multipart::MultipartError;
 use axum::http::HeaderValue;
 use axum::response::Response;
 use axum::Extension;
@@ -9,25 +9,26 @@ use axum::{
     extract::State,
     http::{header, StatusCode},
     response::IntoResponse,
-    Json,
 };
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use rand_core::OsRng;
-use std::collections::HashSet;
 use std::sync::Arc;
 
-use crate::config::Config;
+use crate::config::Registration;
+use crate::resources::{avatar, generated};
 use crate::state::AppState;
+use crate::storage::blobs;
 use crate::{
     db,
-    db::schema::users,
-    db::user::{NewUser, User},
+    db::user::{NewUser, UserChanges},
+    repository::{AvatarChange, Page, Read},
 };
 
-use super::errors::ApiError;
+use super::errors::{ApiError, Problem, UploadError};
+use super::extract::{Json, Multipart, Path, Query};
 use super::token::TokenClaims;
 
-#[derive(Debug, utoipa::ToSchema)]
+#[derive(Debug)]
 pub enum UserError {
     Exists,
     HashPassword,
@@ -36,6 +37,7 @@ pub enum UserError {
     InvalidCredentials,
     NotFound,
     Unauthorized,
+    RegistrationClosed,
 }
 
 impl std::error::Error for UserError {}
@@ -50,12 +52,29 @@ impl std::fmt::Display for UserError {
             Self::InvalidCredentials => write!(f, "Invalid user credentials"),
             Self::NotFound => write!(f, "User not found"),
             Self::Unauthorized => write!(f, "User is not authorized"),
+            Self::RegistrationClosed => write!(f, "Registration of new users is closed"),
+        }
+    }
+}
+
+impl UserError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Exists => "user.exists",
+            Self::HashPassword => "user.hash_password",
+            Self::ParseUuid => "user.invalid_id",
+            Self::MissedCredentials => "user.missing_credentials",
+            Self::InvalidCredentials => "user.invalid_credentials",
+            Self::NotFound => "user.not_found",
+            Self::Unauthorized => "user.unauthorized",
+            Self::RegistrationClosed => "user.registration_closed",
         }
     }
 }
 
 pub mod schema {
     use crate::db::user;
+    use crate::resources::generated::Style;
 
     #[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
     pub struct NewUser {
@@ -71,7 +90,21 @@ pub mod schema {
         pub name: String,
         pub email: String,
         pub is_admin: bool,
+        /// Id to fetch from `/resources/avatars`, the user id itself when nothing was uploaded.
         pub avatar: String,
+        pub avatar_style: Style,
+        pub created_at: String,
+        pub updated_at: String,
+    }
+
+    /// A user as they and the admins see them, with their activity.
+    #[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+    pub struct Me {
+        #[serde(flatten)]
+        pub user: User,
+        pub last_login_at: Option<String>,
+        /// Last authenticated request, up to a few minutes behind.
+        pub last_seen_at: Option<String>,
     }
 
     #[derive(serde::Deserialize, utoipa::ToSchema)]
@@ -86,6 +119,11 @@ pub mod schema {
         pub password: String,
     }
 
+    #[derive(serde::Deserialize, utoipa::ToSchema)]
+    pub struct AvatarStyle {
+        pub style: Style,
+    }
+
     #[derive(serde::Deserialize, utoipa::ToSchema)]
     pub struct Avatar {
         pub content: String,
@@ -100,7 +138,23 @@ pub mod schema {
                 name: user.name.to_owned(),
                 email: user.email.to_owned(),
                 is_admin: user.is_admin,
-                avatar: user.avatar.to_owned(),
+                avatar: match user.avatar.is_empty() {
+                    true => user.id.to_string(),
+                    false => user.avatar.to_owned(),
+                },
+                avatar_style: user.avatar_style.parse().unwrap_or(Style::Identicon),
+                created_at: user.created_at.to_rfc3339(),
+                updated_at: user.updated_at.to_rfc3339(),
+            }
+        }
+    }
+
+    impl Me {
+        pub fn from(user: &user::User) -> Self {
+            Me {
+                user: User::from(user),
+                last_login_at: user.last_login_at.map(|time| time.to_rfc3339()),
+                last_seen_at: user.last_seen_at.map(|time| time.to_rfc3339()),
             }
         }
     }
@@ -113,72 +167,45 @@ pub mod schema {
 }
 
 #[utoipa::path(get, path = "/api/user/all",
+    params(Page),
     responses((status = 200, body = [User]))
 )]
-pub async fn all(State(state): State<Arc<AppState>>) -> Result<Json<Vec<schema::User>>, ApiError> {
-    use diesel::prelude::*;
-
-    let users = db::execute(&state.database, move |conn| {
-        users::table.select(User::as_select()).get_results(conn)
-    })
-    .await?
-    .into_iter()
-    .map(|ref user| schema::User::from(user))
-    .collect::<Vec<schema::User>>();
+pub async fn all(
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<Page>,
+) -> Result<Json<Vec<schema::User>>, ApiError> {
+    let users = state
+        .users
+        .list(page)
+        .await?
+        .iter()
+        .map(schema::User::from)
+        .collect();
 
     Ok(Json(users))
 }
 
 #[utoipa::path(post, path = "/api/user/register", 
     request_body = NewUser,
-    responses((status = 200, body = User), (status = 500, body = ApiError))
+    responses((status = 200, body = User), (status = "4XX", response = Problem), (status = 500, response = Problem))
 )]
 pub async fn register(
     State(state): State<Arc<AppState>>,
     Json(body): Json<schema::NewUser>,
 ) -> Result<Json<schema::User>, ApiError> {
-    use diesel::prelude::*;
-
-    let count = db::execute(&state.database, move |conn| {
-        users::table.into_boxed().count().get_result::<i64>(conn)
-    })
-    .await?;
-
-    let (login, email) = (body.login.clone(), body.email.clone());
-    let user = db::execute(&state.database, move |conn| {
-        users::table
-            .into_boxed()
-            .filter(users::login.eq(login).or(users::email.eq(email)))
-            .first::<User>(conn)
-            .optional()
-    })
-    .await?;
-
-    if user.is_some() {
-        return Err(ApiError::Query(UserError::Exists));
-    }
-
-    let hashed_password = Argon2::default()
-        .hash_password(body.password.as_bytes(), &SaltString::generate(&mut OsRng))
-        .map_err(|_| ApiError::Query(UserError::HashPassword))
-        .map(|hash| hash.to_string())?;
+    let open = state.settings().registration == Registration::Open;
+    let hashed_password = hash_password(&body.password).map_err(ApiError::Query)?;
 
     let new_user = NewUser {
-        login: body.login.clone(),
+        login: body.login.to_owned(),
         hashed_password,
         name: body.login,
         email: body.email,
-        is_admin: count == 0,
+        is_admin: false,
         avatar: String::default(),
+        avatar_style: generated::Style::Identicon.as_str().to_string(),
     };
-
-    let user = db::execute(&state.database, move |conn| {
-        diesel::insert_into(users::table)
-            .values(new_user)
-            .returning(User::as_returning())
-            .get_result(conn)
-    })
-    .await?;
+    let user = state.users.register(new_user, open).await?;
 
     Ok(Json(schema::User::from(&user)))
 }
@@ -191,43 +218,30 @@ pub async fn remove(
     State(state): State<Arc<AppState>>,
     Json(body): Json<schema::RemoveUser>,
 ) -> Result<(), ApiError> {
-    use diesel::prelude::*;
-
     let uuid =
         uuid::Uuid::parse_str(&body.id).map_err(|_| ApiError::Query(UserError::ParseUuid))?;
 
-    db::execute(&state.database, move |conn| {
-        diesel::delete(users::table.filter(users::id.eq(uuid))).execute(conn)
-    })
-    .await?;
+    delete(&state, uuid).await?;
 
     Ok(())
 }
 
 #[utoipa::path(post, path = "/api/user/login",
     request_body = LoginUser,
-    responses((status = 200, body = User), (status = "4XX", body = UserError), (status = 500, body = ApiError))
+    responses((status = 200, body = Me), (status = "4XX", response = Problem), (status = 500, response = Problem))
 )]
 pub async fn login(
     State(state): State<Arc<AppState>>,
     Json(body): Json<schema::LoginUser>,
 ) -> Result<impl IntoResponse, ApiError> {
-    use diesel::prelude::*;
-
-    let query = users::table.into_boxed().select(User::as_select());
-    let query = if let Some(login) = body.login {
-        query.filter(users::login.eq(login))
-    } else if let Some(email) = body.email {
-        query.filter(users::email.eq(email))
+    let user = if let Some(login) = &body.login {
+        state.users.find_by_login(login, Read::Primary).await?
+    } else if let Some(email) = &body.email {
+        state.users.find_by_email(email).await?
     } else {
         return Err(ApiError::Query(UserError::MissedCredentials));
     };
 
-    let user = db::execute(&state.database, move |conn| {
-        query.first::<User>(conn).optional()
-    })
-    .await?;
-
     let user = match user {
         Some(user) => user,
         None => return Err(ApiError::Query(UserError::InvalidCredentials)),
@@ -236,16 +250,22 @@ pub async fn login(
     if !match PasswordHash::new(&user.hashed_password) {
         Ok(parsed_hash) => Argon2::default()
             .verify_password(body.password.as_bytes(), &parsed_hash)
-            .map_or(false, |_| true),
+            .is_ok(),
         Err(_) => false,
     } {
         return Err(ApiError::Query(UserError::InvalidCredentials));
     }
 
+    let user = state
+        .users
+        .record_login(user.id)
+        .await?
+        .ok_or(ApiError::Query(UserError::InvalidCredentials))?;
+
     let token = TokenClaims::create(
         user.id.to_string(),
-        state.config.jwt.secret.to_owned(),
-        state.config.jwt.maxage,
+        state.config.load().jwt.secret.to_owned(),
+        state.config.load().jwt.maxage,
     )
     .unwrap();
 
@@ -256,7 +276,7 @@ pub async fn login(
         .secure(true)
         .http_only(true);
 
-    let mut response = Json(schema::User::from(&user)).into_response();
+    let mut response = Json(schema::Me::from(&user)).into_response();
     response
         .headers_mut()
         .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
@@ -285,58 +305,56 @@ pub async fn logout() -> Result<axum::response::Response, ApiError> {
     Ok(response)
 }
 
+/// Activity is only shown to the user themselves and to admins.
 #[utoipa::path(get, path = "/api/user/{login}", 
     params(("login", Path,)), 
-    responses((status = 404, body = UserError), (status = 500, body = ApiError))
+    responses((status = 200, body = User), (status = 404, response = Problem), (status = 500, response = Problem))
 )]
 pub async fn profile(
     State(state): State<Arc<AppState>>,
     Extension(user_id): Extension<Option<uuid::Uuid>>,
     Path(login): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
-    use diesel::prelude::*;
-
-    // TODO: Current user priveleges
-    let user = db::execute(&state.database, move |conn| {
-        users::table
-            .into_boxed()
-            .filter(users::login.eq(login))
-            .first::<User>(conn)
-            .optional()
-    })
-    .await?;
+    let user = match state
+        .users
+        .find_by_login(&login, Read::Replica(user_id))
+        .await?
+    {
+        Some(user) => user,
+        None => return Err(ApiError::Query(UserError::NotFound)),
+    };
 
-    match user {
-        Some(user) => Ok(Json(schema::User::from(&user))),
-        None => Err(ApiError::Query(UserError::NotFound)),
-    }
+    let privileged = match user_id {
+        Some(viewer) if viewer == user.id => true,
+        Some(viewer) => state
+            .users
+            .find(viewer, Read::Replica(Some(viewer)))
+            .await?
+            .is_some_and(|viewer| viewer.is_admin),
+        None => false,
+    };
+
+    Ok(match privileged {
+        true => Json(schema::Me::from(&user)).into_response(),
+        false => Json(schema::User::from(&user)).into_response(),
+    })
 }
 
 #[utoipa::path(get, path = "/api/user/current", 
-    security(("token" = []))
+    security(("token" = [])),
+    responses((status = 200, body = Me), (status = "4XX", response = Problem), (status = 500, response = Problem))
 )]
 pub async fn current(
     State(state): State<Arc<AppState>>,
     Extension(user_id): Extension<Option<uuid::Uuid>>,
 ) -> Result<impl IntoResponse, ApiError> {
-    use diesel::prelude::*;
-
     let uuid = match user_id {
         Some(user_id) => user_id,
         None => return Err(ApiError::Query(UserError::Unauthorized)),
     };
 
-    let user = db::execute(&state.database, move |conn| {
-        users::table
-            .into_boxed()
-            .filter(users::id.eq(uuid))
-            .first::<User>(conn)
-            .optional()
-    })
-    .await?;
-
-    match user {
-        Some(user) => Ok(Json(schema::User::from(&user))),
+    match state.users.find(uuid, Read::Replica(Some(uuid))).await? {
+        Some(user) => Ok(Json(schema::Me::from(&user))),
         None => Err(ApiError::Query(UserError::NotFound)),
     }
 }
@@ -345,7 +363,11 @@ pub async fn current(
 #[utoipa::path(post, path = "/api/user/avatar",
     security(("token" = [])),
     request_body(content = Image, content_type = "multipart/form-data"),
-    responses((status = 200), (status = "4XX", body = UserError), (status = 500, body = ApiError))
+    responses(
+        (status = 200),
+        (status = "4XX", response = Problem),
+        (status = 500, response = Problem)
+    )
 )]
 pub async fn avatar(
     State(state): State<Arc<AppState>>,
@@ -353,90 +375,195 @@ pub async fn avatar(
     //Json(body): Json<Avatar>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, ApiError> {
-    use diesel::prelude::*;
-
     let uuid = match user_id {
         Some(user_id) => user_id,
         None => return Err(ApiError::Query(UserError::Unauthorized)),
     };
 
-    let user = db::execute(&state.database, move |conn| {
-        users::table
-            .into_boxed()
-            .filter(users::id.eq(uuid))
-            .first::<User>(conn)
-            .optional()
+    if state.users.find(uuid, Read::Primary).await?.is_none() {
+        return Err(ApiError::Query(UserError::NotFound));
+    }
+
+    let data: Bytes = match multipart.next_field().await.map_err(upload_error)? {
+        Some(field) => field.bytes().await.map_err(upload_error)?,
+        None => return Err(UploadError::Missing.into()),
+    };
+
+    if data.is_empty() {
+        return Err(UploadError::Missing.into());
+    }
+    if data.len() > state.settings().avatar_max_size {
+        return Err(UploadError::TooLarge.into());
+    }
+
+    let avatar_id = blobs::hash(&data);
+    let size = data.len() as i64;
+
+    let hash = avatar_id.clone();
+    let acquired = db::execute(&state.database, move |conn| {
+        db::blob::acquire(conn, &hash, size)
     })
     .await?;
 
-    let user = match user {
-        Some(user) => user,
-        None => return Err(ApiError::Query(UserError::NotFound)),
+    // Identical uploads are rendered and stored only once.
+    if acquired.ref_count <= 1 || !avatar::is_stored(state.storage.as_ref(), &avatar_id).await? {
+        let max_dimension = state.config.load().uploads.avatar_max_dimension;
+        let stored =
+            match tokio::task::spawn_blocking(move || avatar::render(&data, max_dimension)).await {
+                Ok(Ok(variants)) => avatar::store(state.storage.as_ref(), &avatar_id, variants)
+                    .await
+                    .map_err(ApiError::from),
+                Ok(Err(e)) => Err(e.into()),
+                Err(_) => Err(UploadError::Encode.into()),
+            };
+
+        if let Err(e) = stored {
+            blobs::release(&state, avatar_id).await;
+            return Err(e);
+        }
+    }
+
+    match state.users.replace_avatar(uuid, avatar_id.clone()).await {
+        Ok(Some(AvatarChange {
+            remaining: Some(0), ..
+        })) => collect_garbage(&state).await,
+        // Avatars uploaded before the blob store are not reference counted.
+        Ok(Some(AvatarChange {
+            previous,
+            remaining: None,
+        })) if !previous.is_empty() => {
+            if let Err(e) = state
+                .storage
+                .delete_prefix(&avatar::prefix(&previous))
+                .await
+            {
+                tracing::warn!("Failed to remove avatar {}: {}", previous, e);
+            }
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            blobs::release(&state, avatar_id).await;
+            return Err(ApiError::Query(UserError::NotFound));
+        }
+        Err(e) => {
+            blobs::release(&state, avatar_id).await;
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(post, path = "/api/user/avatar/style",
+    security(("token" = [])),
+    request_body = AvatarStyle,
+    responses((status = 200), (status = "4XX", response = Problem), (status = 500, response = Problem))
+)]
+pub async fn avatar_style(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Json(body): Json<schema::AvatarStyle>,
+) -> Result<(), ApiError> {
+    let uuid = match user_id {
+        Some(user_id) => user_id,
+        None => return Err(ApiError::Query(UserError::Unauthorized)),
     };
 
-    let data: Bytes = if let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|_| ApiError::ReadContent)?
-    {
-        /*if field.name().unwrap() != "file" {
-            continue;
-        }*/
-        field.bytes().await.map_err(|_| ApiError::ReadContent)?
-    } else {
-        return Err(ApiError::ReadContent);
+    let changes = UserChanges {
+        avatar_style: Some(body.style.as_str().to_string()),
+        ..UserChanges::default()
     };
 
-    let avatars = db::execute(&state.database, move |conn| {
-        users::table
-            .into_boxed()
-            .select(users::avatar)
-            .get_results::<String>(conn)
-    })
-    .await?
-    .into_iter()
-    .filter(|avatar_hash| !avatar_hash.is_empty())
-    .collect::<Vec<String>>();
-
-    let avatar_id = sqids::Sqids::builder()
-        .min_length(10)
-        .blocklist(HashSet::from_iter(avatars.clone().into_iter()))
-        .build()
-        .unwrap()
-        .encode(&[avatars.len() as u64])
-        .unwrap();
+    match state.users.update(uuid, changes).await? {
+        Some(_) => Ok(()),
+        None => Err(ApiError::Query(UserError::NotFound)),
+    }
+}
 
-    let reader = image::io::Reader::new(std::io::Cursor::new(data))
-        .with_guessed_format()
-        .unwrap();
-    let format = reader.format().unwrap();
-    let img = reader.decode().unwrap();
-
-    img.save_with_format(
-        Config::data_dir()
-            .unwrap()
-            .join("avatars")
-            .join(avatar_id.clone()),
-        format,
-    )
-    .unwrap();
+pub(super) fn upload_error(e: MultipartError) -> ApiError {
+    match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => UploadError::TooLarge.into(),
+        _ => ApiError::ReadContent,
+    }
+}
 
-    if !user.avatar.is_empty() {
-        std::fs::remove_file(
-            Config::data_dir()
-                .unwrap()
-                .join("avatars")
-                .join(user.avatar.clone()),
-        )
-        .unwrap();
+/// Hashes a password for storing in `users.hashed_password`.
+pub(crate) fn hash_password(password: &str) -> Result<String, UserError> {
+    Argon2::default()
+        .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+        .map(|hash| hash.to_string())
+        .map_err(|_| UserError::HashPassword)
+}
+
+/// Deletes the user with their files and releases the blobs they referenced.
+///
+/// Returns whether the user existed.
+pub(crate) async fn delete(state: &AppState, user_id: uuid::Uuid) -> Result<bool, ApiError> {
+    let deleted = state.users.delete(user_id).await?;
+
+    if deleted.as_ref().is_some_and(|deleted| deleted.unreferenced) {
+        collect_garbage(state).await;
     }
 
-    db::execute(&state.database, move |conn| {
-        diesel::update(&user)
-            .set(users::avatar.eq(avatar_id))
-            .execute(conn)
-    })
-    .await?;
+    Ok(deleted.is_some())
+}
 
-    Ok(())
+async fn collect_garbage(state: &AppState) {
+    if let Err(e) = blobs::collect_garbage(&state.database, state.storage.clone()).await {
+        tracing::warn!("Failed to collect unreferenced blobs: {}", e);
+    }
+}
+
+#[tokio::test]
+async fn test_register() {
+    let config = crate::config::Config::default();
+    let state = Arc::new(AppState {
+        database: db::create_pool(&config).unwrap(),
+        storage: Arc::new(crate::storage::local::Local::new(std::env::temp_dir())),
+        users: Arc::new(crate::repository::memory::Memory::new()),
+        config: arc_swap::ArcSwap::from_pointee(config),
+        settings: arc_swap::ArcSwap::from_pointee(Default::default()),
+        seen: Default::default(),
+        appending: Default::default(),
+    });
+    let body = |login: &str| {
+        Json(schema::NewUser {
+            login: login.to_string(),
+            password: String::from("password"),
+            email: format!("{}@elnafo.ru", login),
+        })
+    };
+
+    let Json(first) = register(State(state.clone()), body("first")).await.unwrap();
+    assert!(first.is_admin);
+    let Json(second) = register(State(state.clone()), body("second"))
+        .await
+        .unwrap();
+    assert!(!second.is_admin);
+    assert!(matches!(
+        register(State(state.clone()), body("second")).await,
+        Err(ApiError::Query(UserError::Exists))
+    ));
+
+    let shows_activity = |viewer: Option<&str>| {
+        let state = state.clone();
+        let viewer = viewer.map(|id| uuid::Uuid::parse_str(id).unwrap());
+        async move {
+            let response = profile(State(state), Extension(viewer), Path("second".to_string()))
+                .await
+                .unwrap()
+                .into_response();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let user: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            user.get("last_seen_at").is_some()
+        }
+    };
+    assert!(!shows_activity(None).await);
+    assert!(shows_activity(Some(&second.id)).await);
+    assert!(shows_activity(Some(&first.id)).await);
+
+    let Json(users) = all(State(state), Query(Page::default())).await.unwrap();
+    assert_eq!(users.len(), 2);
 }
@@ -0,0 +1,83 @@
+use crate::config::errors::ConfigError;
+use crate::db::errors::DatabaseError;
+use std::error::Error as StdError;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum BackupError {
+    IO(std::io::Error),
+    Database(DatabaseError),
+    Config(ConfigError),
+    /// The file is not a backup archive or is damaged.
+    Invalid(String),
+    /// The archive was written in a layout this version does not read.
+    Format(u32),
+    /// The dumped rows fit another schema than the database has, by last migration.
+    Schema {
+        archive: Option<String>,
+        database: Option<String>,
+    },
+    /// Restoring would replace existing data without being asked to.
+    NotEmpty,
+}
+
+impl StdError for BackupError {}
+
+impl Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let schema = |name: &Option<String>| name.to_owned().unwrap_or(String::from("none"));
+
+        match self {
+            Self::IO(ref e) => e.fmt(f),
+            Self::Database(ref e) => e.fmt(f),
+            Self::Config(ref e) => e.fmt(f),
+            Self::Invalid(ref e) => write!(f, "Invalid backup archive: {}", e),
+            Self::Format(format) => write!(
+                f,
+                "Backup archive format {} is not supported, expected {}",
+                format,
+                super::FORMAT
+            ),
+            Self::Schema { archive, database } => write!(
+                f,
+                "Backup was made at migration {}, the database is at {}; restore it with the matching version",
+                schema(archive),
+                schema(database)
+            ),
+            Self::NotEmpty => write!(
+                f,
+                "The database is not empty, use --force to replace its content"
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        Self::IO(e)
+    }
+}
+
+impl From<DatabaseError> for BackupError {
+    fn from(e: DatabaseError) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<ConfigError> for BackupError {
+    fn from(e: ConfigError) -> Self {
+        Self::Config(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Invalid(e.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for BackupError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Self::IO(std::io::Error::other(e))
+    }
+}
@@ -0,0 +1,363 @@
+//! Backups as a single `.tar.gz` archive: a manifest, a logical dump of the database,
+//! the uploads of the local storage and the configuration without its secrets.
+
+pub mod errors;
+pub mod schedule;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, StorageBackend};
+use crate::db::{self, dump::Dump, errors::DatabaseError, Isolation, MigrationState, Pool};
+
+use errors::BackupError;
+
+/// Version of the archive layout, restoring requires the same one.
+pub const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const CONFIG: &str = "config.toml";
+const DATABASE: &str = "database.json";
+const UPLOADS: &str = "uploads";
+
+/// Describes an archive, stored as its first entry.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    /// Version of elnafo that wrote the archive.
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    /// Last applied migration, the schema the dumped rows fit.
+    pub schema: Option<String>,
+    /// Number of rows of every table.
+    pub tables: BTreeMap<String, usize>,
+    /// Whether the uploads are included; they are not with the S3 backend.
+    pub uploads: bool,
+}
+
+/// `<dir>/<prefix>-<UTC time>.tar.gz`, sorting by age.
+pub fn file_name(dir: &Path, prefix: &str) -> PathBuf {
+    dir.join(format!(
+        "{}-{}.tar.gz",
+        prefix,
+        Utc::now().format("%Y%m%d-%H%M%S")
+    ))
+}
+
+/// Writes a backup of the database, the uploads and the configuration to `path`.
+pub async fn create(config: &Config, pool: &Pool, path: &Path) -> Result<Manifest, BackupError> {
+    let schema = schema(pool).await?;
+    let dump = db::transaction(pool, Isolation::RepeatableRead, Dump::read).await?;
+
+    let uploads = match config.storage.backend {
+        StorageBackend::Local => Some(config.paths.uploads()?),
+        StorageBackend::S3 => {
+            tracing::warn!("Uploads are kept by the S3 backend and are not included in the backup");
+            None
+        }
+    };
+
+    let manifest = Manifest {
+        format: FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        schema,
+        tables: dump
+            .counts()
+            .into_iter()
+            .map(|(table, rows)| (table.to_string(), rows))
+            .collect(),
+        uploads: uploads.is_some(),
+    };
+    let config = config.redacted().to_string()?;
+
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        write(&path, &manifest, &config, &dump, uploads.as_deref()).map(|_| manifest)
+    })
+    .await?
+}
+
+/// Replaces the database content and adds the uploads from the archive at `path`.
+///
+/// Pending migrations are applied first, the archive has to match the resulting schema.
+/// Unless `force`, only an empty database is restored into.
+pub async fn restore(
+    config: &Config,
+    pool: &Pool,
+    path: &Path,
+    force: bool,
+) -> Result<Manifest, BackupError> {
+    let (manifest, dump) = {
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || read(&path)).await??
+    };
+
+    db::run_migrations(pool).await?;
+    let schema = schema(pool).await?;
+    if schema != manifest.schema {
+        return Err(BackupError::Schema {
+            archive: manifest.schema,
+            database: schema,
+        });
+    }
+
+    if !force && !db::execute(pool, db::dump::is_empty).await? {
+        return Err(BackupError::NotEmpty);
+    }
+
+    if manifest.uploads {
+        match config.storage.backend {
+            StorageBackend::Local => {
+                let path = path.to_owned();
+                let uploads = config.paths.uploads()?;
+                let unpacked =
+                    tokio::task::spawn_blocking(move || unpack_uploads(&path, &uploads)).await??;
+                tracing::info!("Restored {} uploaded files", unpacked);
+            }
+            StorageBackend::S3 => {
+                tracing::warn!("The S3 backend is in use, the uploads in the archive are skipped")
+            }
+        }
+    }
+
+    let dump = Arc::new(dump);
+    db::transaction(pool, Isolation::ReadCommitted, move |conn| dump.write(conn)).await?;
+
+    Ok(manifest)
+}
+
+/// Last applied migration, refusing databases migrated by a newer version.
+async fn schema(pool: &Pool) -> Result<Option<String>, BackupError> {
+    let status = db::migration_status(pool).await?;
+
+    let unknown = status
+        .iter()
+        .filter(|(_, state)| *state == MigrationState::Unknown)
+        .map(|(version, _)| version.to_owned())
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(DatabaseError::UnknownMigrations(unknown).into());
+    }
+
+    Ok(status
+        .into_iter()
+        .filter(|(_, state)| *state == MigrationState::Applied)
+        .map(|(name, _)| name)
+        .next_back())
+}
+
+fn write(
+    path: &Path,
+    manifest: &Manifest,
+    config: &str,
+    dump: &Dump,
+    uploads: Option<&Path>,
+) -> Result<(), BackupError> {
+    // Write next to the target and rename, so a failed backup never looks complete.
+    let temp = path.with_file_name(format!(
+        ".{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+
+    let written = (|| -> Result<(), BackupError> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The dump holds the password hashes of all users.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let encoder = GzEncoder::new(BufWriter::new(options.open(&temp)?), Compression::default());
+        let mut archive = tar::Builder::new(encoder);
+        archive.follow_symlinks(false);
+
+        append(
+            &mut archive,
+            MANIFEST,
+            &serde_json::to_vec_pretty(manifest)?,
+        )?;
+        append(&mut archive, CONFIG, config.as_bytes())?;
+        append(&mut archive, DATABASE, &serde_json::to_vec(dump)?)?;
+        if let Some(uploads) = uploads.filter(|dir| dir.is_dir()) {
+            archive.append_dir_all(UPLOADS, uploads)?;
+        }
+
+        let mut file = archive.into_inner()?.finish()?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+
+        Ok(())
+    })();
+
+    match written {
+        Ok(()) => Ok(fs::rename(&temp, path)?),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+fn append<W: Write>(archive: &mut tar::Builder<W>, name: &str, content: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+
+    archive.append_data(&mut header, name, content)
+}
+
+fn open(path: &Path) -> io::Result<tar::Archive<impl Read>> {
+    Ok(tar::Archive::new(GzDecoder::new(BufReader::new(
+        fs::File::open(path)?,
+    ))))
+}
+
+/// Reads the manifest and the dump, checking the format before the rest is parsed.
+fn read(path: &Path) -> Result<(Manifest, Dump), BackupError> {
+    let mut archive = open(path)?;
+    let mut manifest: Option<Manifest> = None;
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+
+        match (name.as_str(), &manifest) {
+            (MANIFEST, _) => {
+                let read = serde_json::from_reader::<_, Manifest>(entry)?;
+                if read.format != FORMAT {
+                    return Err(BackupError::Format(read.format));
+                }
+                manifest = Some(read);
+            }
+            (DATABASE, Some(_)) => {
+                let dump = serde_json::from_reader(entry)?;
+                return Ok((manifest.unwrap(), dump));
+            }
+            (DATABASE, None) => {
+                return Err(BackupError::Invalid(format!(
+                    "{} is missing or not first",
+                    MANIFEST
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    Err(BackupError::Invalid(format!(
+        "{} is missing",
+        match manifest {
+            Some(_) => DATABASE,
+            None => MANIFEST,
+        }
+    )))
+}
+
+/// Extracts the uploads into `dir` and returns the number of files.
+fn unpack_uploads(path: &Path, dir: &Path) -> Result<usize, BackupError> {
+    let mut archive = open(path)?;
+    let mut unpacked = 0;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let Ok(relative) = name.strip_prefix(UPLOADS) else {
+            continue;
+        };
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(BackupError::Invalid(format!(
+                "{} escapes the uploads",
+                name.display()
+            )));
+        }
+
+        let target = dir.join(relative);
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => fs::create_dir_all(&target)?,
+            tar::EntryType::Regular => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                entry.unpack(&target)?;
+                unpacked += 1;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(unpacked)
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_backup_restore_sqlite() {
+    use crate::db::user::NewUser;
+    use crate::repository::{sql::Sql, Read, UserRepository};
+
+    let root = std::env::temp_dir().join(format!("elnafo-test-backup-{}", std::process::id()));
+    let setup = |name: &str| {
+        let mut config = Config::default();
+        config.paths.data = Some(root.join(name));
+        config.database.url = Some(String::from("sqlite://elnafo.db"));
+        fs::create_dir_all(config.paths.uploads().unwrap()).unwrap();
+        let pool = db::create_pool(&config).unwrap();
+        (config, pool)
+    };
+
+    let (config, pool) = setup("source");
+    db::run_migrations(&pool).await.unwrap();
+    let user = Sql::new(pool.clone())
+        .register(
+            NewUser {
+                login: String::from("admin"),
+                hashed_password: String::from("hash"),
+                name: String::from("admin"),
+                email: String::from("admin@elnafo.ru"),
+                is_admin: false,
+                avatar: String::new(),
+                avatar_style: String::new(),
+            },
+            false,
+        )
+        .await
+        .unwrap();
+    fs::write(config.paths.uploads().unwrap().join("file"), b"content").unwrap();
+
+    let archive = root.join("backup.tar.gz");
+    let manifest = create(&config, &pool, &archive).await.unwrap();
+    assert_eq!(manifest.tables["users"], 1);
+    assert!(manifest.uploads);
+
+    let (config, pool) = setup("target");
+    restore(&config, &pool, &archive, false).await.unwrap();
+    assert!(matches!(
+        restore(&config, &pool, &archive, false).await,
+        Err(BackupError::NotEmpty)
+    ));
+    restore(&config, &pool, &archive, true).await.unwrap();
+
+    let restored = Sql::new(pool)
+        .find(user.id, Read::Primary)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(restored.login, "admin");
+    assert!(restored.is_admin);
+    assert_eq!(
+        fs::read(config.paths.uploads().unwrap().join("file")).unwrap(),
+        b"content"
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
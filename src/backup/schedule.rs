@@ -0,0 +1,92 @@
+//! Periodic backups into `paths.backups`, following configuration reloads.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::config::{validate::parse_duration, Config};
+use crate::state::AppState;
+
+use super::errors::BackupError;
+
+/// Prefix of the archives the schedule writes and rotates.
+const PREFIX: &str = "scheduled";
+
+/// How often the schedule checks whether a backup is due.
+const TICK: Duration = Duration::from_secs(60);
+
+/// Writes a backup whenever the newest scheduled one is older than `backup.interval`,
+/// then deletes the oldest beyond `backup.keep`.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let config = state.config.load_full();
+
+        if let Some(interval) = interval(&config) {
+            match due(&config, interval).await {
+                Ok(true) => match backup(&config, &state).await {
+                    Ok(path) => tracing::info!("Wrote backup {}", path.display()),
+                    Err(e) => tracing::error!("Scheduled backup failed: {}", e),
+                },
+                Ok(false) => {}
+                Err(e) => tracing::error!("Failed to list backups: {}", e),
+            }
+        }
+
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+fn interval(config: &Config) -> Option<Duration> {
+    match config.backup.interval.as_str() {
+        "" => None,
+        interval => parse_duration(interval).ok(),
+    }
+}
+
+async fn due(config: &Config, interval: Duration) -> Result<bool, BackupError> {
+    let newest = match scheduled(&config.paths.backups()?).await?.last() {
+        Some(path) => tokio::fs::metadata(path).await?.modified()?,
+        None => return Ok(true),
+    };
+
+    Ok(SystemTime::now()
+        .duration_since(newest)
+        .is_ok_and(|age| age >= interval))
+}
+
+async fn backup(config: &Config, state: &AppState) -> Result<PathBuf, BackupError> {
+    let dir = config.paths.backups_dir()?;
+    dir.create()?;
+    let dir = dir.path;
+
+    let path = super::file_name(&dir, PREFIX);
+    super::create(config, &state.database, &path).await?;
+
+    let archives = scheduled(&dir).await?;
+    for old in &archives[..archives.len().saturating_sub(config.backup.keep)] {
+        tokio::fs::remove_file(old).await?;
+        tracing::info!("Deleted old backup {}", old.display());
+    }
+
+    Ok(path)
+}
+
+/// Archives written by the schedule, oldest first.
+async fn scheduled(dir: &Path) -> Result<Vec<PathBuf>, BackupError> {
+    let mut archives = Vec::new();
+
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(archives),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&format!("{}-", PREFIX)) && name.ends_with(".tar.gz") {
+            archives.push(entry.path());
+        }
+    }
+    archives.sort();
+
+    Ok(archives)
+}
@@ -0,0 +1,25 @@
+use clap::Subcommand;
+
+use crate::config::Config;
+use crate::storage::blobs;
+
+use super::Error;
+
+#[derive(Debug, Subcommand)]
+pub enum Avatars {
+    /// Delete stored avatars and files no longer referenced by anyone
+    Gc,
+}
+
+pub async fn run(command: Avatars, config: Config) -> Result<(), Error> {
+    let state = super::state(config).await?;
+
+    match command {
+        Avatars::Gc => {
+            let collected = blobs::collect_garbage(&state.database, state.storage.clone()).await?;
+            println!("Collected {} unreferenced blobs", collected.len());
+        }
+    }
+
+    Ok(())
+}
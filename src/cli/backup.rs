@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::backup::{self, Manifest};
+use crate::config::Config;
+use crate::db;
+
+use super::Error;
+
+#[derive(Debug, Args)]
+pub struct Backup {
+    /// Archive to write [default: <paths.backups>/elnafo-<time>.tar.gz]
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct Restore {
+    /// Archive written by `backup`
+    #[arg(value_name = "FILE")]
+    pub archive: PathBuf,
+
+    /// Replace the content of a database that is not empty
+    #[arg(long)]
+    pub force: bool,
+}
+
+pub async fn backup(command: Backup, config: Config) -> Result<(), Error> {
+    let pool = db::create_pool(&config)?;
+
+    let path = match command.output {
+        Some(path) => path,
+        None => {
+            let dir = config.paths.backups_dir()?;
+            dir.create()?;
+            backup::file_name(&dir.path, "elnafo")
+        }
+    };
+
+    let manifest = backup::create(&config, &pool, &path).await?;
+    println!("Wrote {}", path.display());
+    print(&manifest);
+
+    Ok(())
+}
+
+pub async fn restore(command: Restore, config: Config) -> Result<(), Error> {
+    // Creates the data directories when restoring on a new machine.
+    config.check()?;
+    let pool = db::create_pool(&config)?;
+
+    let manifest = backup::restore(&config, &pool, &command.archive, command.force).await?;
+    println!(
+        "Restored {} made by elnafo {} at {}",
+        command.archive.display(),
+        manifest.version,
+        manifest.created_at.to_rfc3339()
+    );
+    print(&manifest);
+
+    Ok(())
+}
+
+fn print(manifest: &Manifest) {
+    for (table, rows) in &manifest.tables {
+        println!("{:>8} rows of {}", rows, table);
+    }
+    if !manifest.uploads {
+        println!("Uploads not included");
+    }
+}
@@ -0,0 +1,107 @@
+use clap::Subcommand;
+
+use crate::config::{Config, Source};
+
+use super::Error;
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write a configuration file with the default values and a random JWT secret
+    /// in the keys directory
+    Init {
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the effective configuration after all layers are applied
+    Show {
+        /// Print secrets instead of redacting them
+        #[arg(long)]
+        secrets: bool,
+    },
+    /// Load and validate the configuration, including database access
+    Check,
+}
+
+pub async fn run(command: ConfigCommand, source: Source) -> Result<(), Error> {
+    match command {
+        ConfigCommand::Init { force } => {
+            let path = source.file()?;
+            if path.exists() && !force {
+                return Err(format!(
+                    "{} already exists, use --force to replace it",
+                    path.display()
+                )
+                .into());
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let mut layered = Config::new()
+                .with_env()?
+                .with_overrides(&source.overrides)?;
+            if let Some(data_dir) = &source.data_dir {
+                layered.paths.data = Some(data_dir.to_owned());
+            }
+            let keys = layered
+                .paths
+                .dirs()?
+                .into_iter()
+                .find(|dir| dir.key == "paths.keys")
+                .ok_or("No keys directory")?;
+            keys.create()?;
+
+            // Keep an existing secret, replacing it would log everyone out.
+            let secret_path = keys.path.join("jwt.secret");
+            if !secret_path.exists() {
+                write_secret(&secret_path, &secret())?;
+                println!("Wrote {}", secret_path.display());
+            }
+
+            // The secret is read from the keys directory instead.
+            let mut config = Config::new();
+            config.jwt.secret = String::new();
+            config.write(&path)?;
+            println!("Wrote {}", path.display());
+        }
+        ConfigCommand::Show { secrets } => {
+            let config = Config::load(&source)?;
+            let config = match secrets {
+                true => config,
+                false => config.redacted(),
+            };
+
+            print!("{}", config.to_string()?);
+        }
+        ConfigCommand::Check => {
+            let config = Config::load(&source)?;
+            config.validate().await?;
+
+            println!("Configuration is valid");
+        }
+    }
+
+    Ok(())
+}
+
+fn secret() -> String {
+    use base64::Engine;
+    use rand_core::RngCore;
+
+    let mut bytes = [0u8; 48];
+    rand_core::OsRng.fill_bytes(&mut bytes);
+
+    base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes)
+}
+
+fn write_secret(path: &std::path::Path, secret: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(secret.as_bytes())
+}
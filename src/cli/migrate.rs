@@ -0,0 +1,56 @@
+use clap::Subcommand;
+
+use crate::config::Config;
+use crate::db::{self, MigrationState};
+
+use super::Error;
+
+#[derive(Debug, Subcommand)]
+pub enum Migrate {
+    /// Apply all pending migrations
+    Up,
+    /// Revert the last applied migrations
+    Down {
+        /// Number of migrations to revert
+        #[arg(short = 'n', long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List migrations and whether they are applied, `?` marking ones unknown to this version
+    Status,
+    /// Revert the last migration and apply it again
+    Redo,
+}
+
+pub async fn run(command: Migrate, config: Config) -> Result<(), Error> {
+    let pool = db::create_pool(&config)?;
+
+    match command {
+        Migrate::Up => print("Applied", db::run_migrations(&pool).await?),
+        Migrate::Down { steps } => print("Reverted", db::revert_migrations(&pool, steps).await?),
+        Migrate::Status => {
+            for (name, state) in db::migration_status(&pool).await? {
+                let mark = match state {
+                    MigrationState::Applied => "x",
+                    MigrationState::Pending => " ",
+                    MigrationState::Unknown => "?",
+                };
+                println!("[{}] {}", mark, name);
+            }
+        }
+        Migrate::Redo => {
+            print("Reverted", db::revert_migrations(&pool, 1).await?);
+            print("Applied", db::run_migrations(&pool).await?);
+        }
+    }
+
+    Ok(())
+}
+
+fn print(action: &str, names: Vec<String>) {
+    if names.is_empty() {
+        println!("Nothing to do");
+    }
+    for name in names {
+        println!("{} {}", action, name);
+    }
+}
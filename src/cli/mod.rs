@@ -0,0 +1,126 @@
+pub mod avatars;
+pub mod backup;
+pub mod config;
+pub mod migrate;
+pub mod user;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use clap::{Args, Parser, Subcommand};
+
+use crate::state::AppState;
+use crate::{db, repository, settings, storage};
+
+pub type Error = Box<dyn std::error::Error>;
+
+/// Command line interface of the elnafo server.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the TOML configuration file
+    /// [default: <data dir>/config.toml with --data-dir, else $XDG_CONFIG_HOME/elnafo/config.toml]
+    #[arg(short, long, env = "ELNAFO_CONFIG", value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Directory for uploads, keys and the cache [default: $XDG_DATA_HOME/elnafo]
+    #[arg(
+        short,
+        long,
+        env = "ELNAFO_DATA_DIR",
+        value_name = "DIR",
+        global = true
+    )]
+    pub data_dir: Option<PathBuf>,
+
+    /// Override a configuration key, taking precedence over the file and environment,
+    /// e.g. `--set server.port=8080`
+    #[arg(short = 's', long = "set", value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the web server, the default without a command
+    Serve,
+    /// Apply, revert or list database migrations
+    #[command(subcommand)]
+    Migrate(migrate::Migrate),
+    /// Create, print or check the configuration
+    #[command(subcommand)]
+    Config(config::ConfigCommand),
+    /// Manage user accounts
+    #[command(subcommand)]
+    User(user::UserCommand),
+    /// Maintain stored avatars
+    #[command(subcommand)]
+    Avatars(avatars::Avatars),
+    /// Write an archive of the database, the uploads and the configuration
+    Backup(backup::Backup),
+    /// Restore the database and the uploads from an archive
+    Restore(backup::Restore),
+}
+
+/// Password of a user, generated and printed when not given.
+#[derive(Debug, Args)]
+pub struct Password {
+    /// Password to set; visible in the process list, prefer --password-stdin
+    #[arg(long, conflicts_with = "password_stdin")]
+    pub password: Option<String>,
+
+    /// Read the password from the first line of the standard input
+    #[arg(long)]
+    pub password_stdin: bool,
+}
+
+impl Password {
+    /// Returns the password and whether it was generated.
+    pub fn read(&self) -> Result<(String, bool), Error> {
+        use base64::Engine;
+        use rand_core::RngCore;
+
+        if let Some(password) = &self.password {
+            return Ok((password.to_owned(), false));
+        }
+
+        if self.password_stdin {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+            let password = line.trim_end_matches(['\r', '\n']).to_string();
+
+            return match password.is_empty() {
+                true => Err("Empty password on standard input".into()),
+                false => Ok((password, false)),
+            };
+        }
+
+        let mut bytes = [0u8; 15];
+        rand_core::OsRng.fill_bytes(&mut bytes);
+
+        Ok((
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes),
+            true,
+        ))
+    }
+}
+
+/// Connects to the database and storage like the server does, without running migrations.
+pub async fn state(config: crate::config::Config) -> Result<Arc<AppState>, Error> {
+    let pool = db::create_pool(&config)?;
+    let storage = storage::from_config(&config)?;
+    let stored = settings::load(&pool).await?;
+
+    Ok(Arc::new(AppState {
+        users: Arc::new(repository::sql::Sql::new(pool.clone())),
+        database: pool,
+        config: ArcSwap::from_pointee(config),
+        storage,
+        settings: ArcSwap::from_pointee(stored),
+        seen: Default::default(),
+        appending: Default::default(),
+    }))
+}
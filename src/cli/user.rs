@@ -0,0 +1,139 @@
+use clap::Subcommand;
+
+use crate::api::user as api;
+use crate::config::Config;
+use crate::db::user::{NewUser, User, UserChanges};
+use crate::repository::{Page, Read, UserRepository};
+use crate::resources::generated;
+
+use super::{Error, Password};
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// Create a user
+    Create {
+        login: String,
+        email: String,
+        /// Grant administrator rights
+        #[arg(long)]
+        admin: bool,
+        #[command(flatten)]
+        password: Password,
+    },
+    /// List all users
+    List,
+    /// Grant or revoke administrator rights
+    SetAdmin {
+        login: String,
+        /// Revoke instead of granting
+        #[arg(long)]
+        revoke: bool,
+    },
+    /// Set a new password
+    ResetPassword {
+        login: String,
+        #[command(flatten)]
+        password: Password,
+    },
+    /// Delete a user with their files and avatar
+    Delete { login: String },
+}
+
+pub async fn run(command: UserCommand, config: Config) -> Result<(), Error> {
+    let state = super::state(config).await?;
+    let users = state.users.as_ref();
+
+    match command {
+        UserCommand::Create {
+            login,
+            email,
+            admin,
+            password,
+        } => {
+            let (password, generated) = password.read()?;
+            let new_user = NewUser {
+                login: login.to_owned(),
+                hashed_password: api::hash_password(&password)?,
+                name: login.to_owned(),
+                email,
+                is_admin: admin,
+                avatar: String::default(),
+                avatar_style: generated::Style::Identicon.as_str().to_string(),
+            };
+
+            let user = users.create(new_user).await?;
+
+            println!("Created user {} ({})", user.login, user.id);
+            if generated {
+                println!("Password: {}", password);
+            }
+        }
+        UserCommand::List => {
+            let users = users.list(Page::default()).await?;
+
+            println!("{:<36}  {:<5}  {:<24}  email", "id", "admin", "login");
+            for user in users {
+                println!(
+                    "{:<36}  {:<5}  {:<24}  {}",
+                    user.id,
+                    if user.is_admin { "yes" } else { "no" },
+                    user.login,
+                    user.email
+                );
+            }
+        }
+        UserCommand::SetAdmin { login, revoke } => {
+            let changes = UserChanges {
+                is_admin: Some(!revoke),
+                ..UserChanges::default()
+            };
+            let user = update(users, &login, changes).await?;
+
+            match user.is_admin {
+                true => println!("{} is now an administrator", user.login),
+                false => println!("{} is no longer an administrator", user.login),
+            }
+        }
+        UserCommand::ResetPassword { login, password } => {
+            let (password, generated) = password.read()?;
+            let changes = UserChanges {
+                hashed_password: Some(api::hash_password(&password)?),
+                ..UserChanges::default()
+            };
+            let user = update(users, &login, changes).await?;
+
+            println!("Password of {} changed", user.login);
+            if generated {
+                println!("Password: {}", password);
+            }
+        }
+        UserCommand::Delete { login } => {
+            let user = find(users, &login).await?;
+            api::delete(&state, user.id).await?;
+
+            println!("Deleted user {} ({})", user.login, user.id);
+        }
+    }
+
+    Ok(())
+}
+
+async fn find(users: &dyn UserRepository, login: &str) -> Result<User, Error> {
+    users
+        .find_by_login(login, Read::Primary)
+        .await?
+        .ok_or_else(|| format!("User {} not found", login).into())
+}
+
+async fn update(
+    users: &dyn UserRepository,
+    login: &str,
+    changes: UserChanges,
+) -> Result<User, Error> {
+    let user = find(users, login).await?;
+
+    users
+        .update(user.id, changes)
+        .await?
+        .ok_or_else(|| format!("User {} not found", login).into())
+}
@@ -0,0 +1,150 @@
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The TOML file is malformed or a value has the wrong type.
+    Parse {
+        file: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        source: Box<toml::de::Error>,
+    },
+    /// An environment variable or command line override could not be applied.
+    Override {
+        origin: String,
+        key: Option<String>,
+        message: String,
+        source: Option<BoxError>,
+    },
+    /// The configuration was loaded but failed validation.
+    Invalid(Vec<Problem>),
+    Serialize(toml::ser::Error),
+    IO {
+        path: Option<PathBuf>,
+        source: std::io::Error,
+    },
+}
+
+/// A single failed check of [`Config::validate`](super::Config::validate).
+#[derive(Debug)]
+pub struct Problem {
+    /// Dotted path of the offending key, e.g. `server.port`.
+    pub key: String,
+    pub message: String,
+    pub source: Option<BoxError>,
+}
+
+impl Problem {
+    pub fn new(key: &str, message: impl Into<String>) -> Self {
+        Problem {
+            key: key.to_string(),
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: impl Into<BoxError>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StdError for ConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Parse { source, .. } => Some(source.as_ref()),
+            Self::Override { source, .. } => source
+                .as_ref()
+                .map(|e| e.as_ref() as &(dyn StdError + 'static)),
+            Self::Invalid(_) => None,
+            Self::Serialize(e) => Some(e),
+            Self::IO { source, .. } => Some(source),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Parse {
+                file,
+                line,
+                column,
+                source,
+            } => {
+                let file = file
+                    .as_ref()
+                    .map_or(String::from("Config"), |file| file.display().to_string());
+
+                write!(
+                    f,
+                    "Failed to parse {}:{}:{}: {}",
+                    file,
+                    line,
+                    column,
+                    source.message().trim()
+                )
+            }
+            Self::Override {
+                origin,
+                key,
+                message,
+                source,
+            } => {
+                write!(f, "Invalid configuration override {}", origin)?;
+                if let Some(key) = key {
+                    write!(f, " for `{}`", key)?;
+                }
+                write!(f, ": {}", message)?;
+                if let Some(source) = source {
+                    write!(f, ": {}", source.to_string().trim())?;
+                }
+
+                Ok(())
+            }
+            Self::Invalid(problems) => {
+                write!(f, "Invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+
+                Ok(())
+            }
+            Self::Serialize(e) => write!(f, "Failed to serialize Config to TOML: {}", e),
+            Self::IO { path, source } => match path {
+                Some(path) => write!(f, "Failed to access {}: {}", path.display(), source),
+                None => write!(f, "Failed to access configuration files: {}", source),
+            },
+        }
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    fn from(e: toml::ser::Error) -> Self {
+        ConfigError::Serialize(e)
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::IO {
+            path: None,
+            source: e,
+        }
+    }
+}
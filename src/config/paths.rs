@@ -0,0 +1,202 @@
+//! Locations of the files the server keeps, following the XDG base directory conventions.
+//!
+//! Without an explicit data directory, data lives in `$XDG_DATA_HOME/elnafo`, the configuration
+//! in `$XDG_CONFIG_HOME/elnafo` and the cache in `$XDG_CACHE_HOME/elnafo`. With one, everything
+//! lives under it. Relative paths in `[paths]` are taken relative to the data directory.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::errors::ConfigError;
+
+const APP: &str = "elnafo";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Paths {
+    /// Data directory, usually given with `--data-dir` or `ELNAFO_DATA_DIR`.
+    pub data: Option<PathBuf>,
+    /// Avatars and files of the local storage backend [default: `<data>/uploads`].
+    pub uploads: Option<PathBuf>,
+    /// Secret files named after their key, e.g. `jwt.secret` [default: `<data>/keys`].
+    pub keys: Option<PathBuf>,
+    /// Disposable data like unfinished uploads [default: `$XDG_CACHE_HOME/elnafo`].
+    pub cache: Option<PathBuf>,
+    /// Scheduled backups [default: `<data>/backups`].
+    pub backups: Option<PathBuf>,
+}
+
+/// A directory the server needs and who may read it.
+pub struct Dir {
+    pub key: &'static str,
+    pub path: PathBuf,
+    /// Unix permission bits, or `None` to leave them to the umask.
+    pub mode: Option<u32>,
+}
+
+impl Paths {
+    pub fn data(&self) -> Result<PathBuf, ConfigError> {
+        match &self.data {
+            Some(path) => absolute(path),
+            None => Ok(xdg("XDG_DATA_HOME", ".local/share")?.join(APP)),
+        }
+    }
+
+    pub fn uploads(&self) -> Result<PathBuf, ConfigError> {
+        self.under_data(&self.uploads, "uploads")
+    }
+
+    pub fn keys(&self) -> Result<PathBuf, ConfigError> {
+        self.under_data(&self.keys, "keys")
+    }
+
+    pub fn backups(&self) -> Result<PathBuf, ConfigError> {
+        self.under_data(&self.backups, "backups")
+    }
+
+    pub fn cache(&self) -> Result<PathBuf, ConfigError> {
+        match (&self.cache, &self.data) {
+            (None, None) => Ok(xdg("XDG_CACHE_HOME", ".cache")?.join(APP)),
+            (cache, _) => self.under_data(cache, "cache"),
+        }
+    }
+
+    /// Chunks of resumable uploads that are not complete yet.
+    pub fn partial_uploads(&self) -> Result<PathBuf, ConfigError> {
+        Ok(self.cache()?.join("uploads"))
+    }
+
+    /// The backups directory, readable only by the server since backups hold the
+    /// password hashes of all users.
+    pub fn backups_dir(&self) -> Result<Dir, ConfigError> {
+        Ok(Dir {
+            key: "paths.backups",
+            path: self.backups()?,
+            mode: Some(0o700),
+        })
+    }
+
+    /// Every directory to create at startup.
+    pub fn dirs(&self) -> Result<Vec<Dir>, ConfigError> {
+        Ok(vec![
+            Dir {
+                key: "paths.data",
+                path: self.data()?,
+                mode: None,
+            },
+            Dir {
+                key: "paths.uploads",
+                path: self.uploads()?,
+                mode: None,
+            },
+            Dir {
+                key: "paths.keys",
+                path: self.keys()?,
+                mode: Some(0o700),
+            },
+            self.backups_dir()?,
+            Dir {
+                key: "paths.cache",
+                path: self.partial_uploads()?,
+                mode: Some(0o700),
+            },
+        ])
+    }
+
+    fn under_data(&self, path: &Option<PathBuf>, default: &str) -> Result<PathBuf, ConfigError> {
+        let data = self.data()?;
+
+        Ok(match path {
+            Some(path) => data.join(path),
+            None => data.join(default),
+        })
+    }
+}
+
+/// The configuration file used without `--config`.
+pub fn config_file(data_dir: Option<&Path>) -> Result<PathBuf, ConfigError> {
+    match data_dir {
+        Some(dir) => Ok(absolute(dir)?.join("config.toml")),
+        None => Ok(xdg("XDG_CONFIG_HOME", ".config")?
+            .join(APP)
+            .join("config.toml")),
+    }
+}
+
+impl Dir {
+    /// Creates the directory with its permissions, tightening them if it already
+    /// exists and is more open than it should be.
+    pub fn create(&self) -> std::io::Result<()> {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+            if let Some(mode) = self.mode {
+                builder.mode(mode);
+                builder.create(&self.path)?;
+
+                let current = std::fs::metadata(&self.path)?.permissions().mode() & 0o777;
+                if current & !mode != 0 {
+                    tracing::warn!(
+                        "Restricting permissions of {} from {:o} to {:o}",
+                        self.path.display(),
+                        current,
+                        mode
+                    );
+                    std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(mode))?;
+                }
+
+                return Ok(());
+            }
+        }
+
+        builder.create(&self.path)
+    }
+}
+
+/// `$var`, or `~/fallback` when it is unset or not absolute as the specification requires.
+fn xdg(var: &str, fallback: &str) -> Result<PathBuf, ConfigError> {
+    if let Some(path) = env::var_os(var).map(PathBuf::from) {
+        if path.is_absolute() {
+            return Ok(path);
+        }
+    }
+
+    match env::var_os("HOME") {
+        Some(home) if !home.is_empty() => Ok(PathBuf::from(home).join(fallback)),
+        _ => Err(ConfigError::IO {
+            path: None,
+            source: std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("neither {} nor HOME is set, pass --data-dir", var),
+            ),
+        }),
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, ConfigError> {
+    Ok(env::current_dir()?.join(path))
+}
+
+#[test]
+fn test_paths() {
+    let paths = Paths {
+        data: Some(PathBuf::from("/srv/elnafo")),
+        keys: Some(PathBuf::from("/etc/elnafo/keys")),
+        ..Paths::default()
+    };
+
+    assert_eq!(paths.uploads().unwrap(), Path::new("/srv/elnafo/uploads"));
+    assert_eq!(paths.keys().unwrap(), Path::new("/etc/elnafo/keys"));
+    assert_eq!(paths.cache().unwrap(), Path::new("/srv/elnafo/cache"));
+    assert_eq!(paths.backups().unwrap(), Path::new("/srv/elnafo/backups"));
+    assert_eq!(
+        config_file(paths.data.as_deref()).unwrap(),
+        Path::new("/srv/elnafo/config.toml")
+    );
+}
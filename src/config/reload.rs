@@ -0,0 +1,159 @@
+//! Live reloading of the configuration on file changes and `SIGHUP`.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use arc_swap::ArcSwap;
+
+use super::errors::ConfigError;
+use super::{lookup, Config, Source};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Keys only read at startup, changing them takes effect after a restart.
+pub const RESTART_REQUIRED: [&str; 6] = [
+    "server.address",
+    "server.port",
+    "database",
+    "storage.backend",
+    "storage.s3",
+    "paths",
+];
+
+/// Keys from [`RESTART_REQUIRED`] whose values differ between the two configurations.
+pub fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let (Ok(old), Ok(new)) = (toml::Value::try_from(old), toml::Value::try_from(new)) else {
+        return Vec::new();
+    };
+
+    RESTART_REQUIRED
+        .into_iter()
+        .filter(|key| {
+            let path = key.split('.').collect::<Vec<&str>>();
+            lookup(&old, &path) != lookup(&new, &path)
+        })
+        .collect()
+}
+
+/// Loads and checks the configuration again, then swaps it in.
+///
+/// Keys that require a restart keep their running values and are reported instead.
+pub fn reload(current: &ArcSwap<Config>, source: &Source) -> Result<Arc<Config>, ConfigError> {
+    let mut config = Config::load(source)?;
+
+    let problems = config.problems();
+    if !problems.is_empty() {
+        return Err(ConfigError::Invalid(problems));
+    }
+
+    let old = current.load();
+    for key in restart_required(&old, &config) {
+        tracing::warn!("{} changed, restart required to apply it", key);
+    }
+
+    config.server.address = old.server.address.to_owned();
+    config.server.port = old.server.port;
+    config.database = old.database.to_owned();
+    config.storage.backend = old.storage.backend;
+    config.storage.s3 = old.storage.s3.to_owned();
+    config.paths = old.paths.to_owned();
+
+    let config = Arc::new(config);
+    current.store(config.clone());
+
+    Ok(config)
+}
+
+/// Reloads the configuration whenever its file changes or the process receives `SIGHUP`,
+/// calling `on_reload` with every configuration swapped in.
+pub async fn watch<F>(current: &ArcSwap<Config>, source: Source, on_reload: F)
+where
+    F: Fn(&Config),
+{
+    let file = match source.file() {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Configuration will not be reloaded: {}", e);
+            return;
+        }
+    };
+    let modified = || -> Option<SystemTime> { std::fs::metadata(&file).ok()?.modified().ok() };
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last_modified = modified();
+
+    loop {
+        #[cfg(unix)]
+        let hangup = async {
+            match hangup.as_mut() {
+                Some(signal) => signal.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = interval.tick() => {
+                let current_modified = modified();
+                if current_modified == last_modified {
+                    continue;
+                }
+                last_modified = current_modified;
+            }
+            _ = hangup => tracing::info!("Received SIGHUP"),
+        }
+
+        match reload(current, &source) {
+            Ok(config) => {
+                on_reload(&config);
+                tracing::info!("Configuration reloaded");
+            }
+            Err(e) => tracing::error!(
+                "Failed to reload configuration, keeping the current one: {}",
+                e
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_restart_required() {
+    let old = Config::default();
+    let mut new = Config::default();
+    new.server.port = 8080;
+    new.database.host = String::from("db");
+    new.uploads.user_quota = 1;
+
+    assert_eq!(restart_required(&old, &new), ["server.port", "database"]);
+}
+
+#[test]
+fn test_reload() {
+    let dir = std::env::temp_dir().join(format!("elnafo-test-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("config.toml");
+    std::fs::write(
+        &file,
+        "[uploads]\nuser_quota = 1000000000000\n\n[paths]\nuploads = \"/srv/elnafo/uploads\"\n",
+    )
+    .unwrap();
+    let source = Source {
+        path: Some(file),
+        data_dir: Some(dir.to_owned()),
+        overrides: Vec::new(),
+    };
+
+    let mut old = Config::load(&source).unwrap();
+    old.paths.uploads = Some(dir.join("uploads"));
+    let current = ArcSwap::from_pointee(old.clone());
+
+    let config = reload(&current, &source).unwrap();
+    assert_eq!(config.uploads.user_quota, 1_000_000_000_000);
+    assert_eq!(config.paths.uploads, old.paths.uploads);
+    assert_eq!(current.load().paths.uploads, old.paths.uploads);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,334 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::db::{self, Engine};
+
+use super::errors::{ConfigError, Problem};
+use super::{Config, StorageBackend};
+
+/// Shortest JWT secret accepted, in bytes.
+pub const MIN_SECRET_LENGTH: usize = 32;
+
+const DEFAULT_SECRET: &str = "change_this_secret";
+
+const SSL_MODES: [&str; 6] = [
+    "disable",
+    "allow",
+    "prefer",
+    "require",
+    "verify-ca",
+    "verify-full",
+];
+
+impl Config {
+    /// Checks the configuration and the environment it depends on, reporting every problem at once.
+    pub async fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = self.local_problems();
+
+        if let Err(problem) = self.check_database().await {
+            problems.push(problem);
+        }
+
+        into_result(problems)
+    }
+
+    /// Like [`Config::validate`], but leaves the database to be waited for.
+    pub fn check(&self) -> Result<(), ConfigError> {
+        into_result(self.local_problems())
+    }
+
+    fn local_problems(&self) -> Vec<Problem> {
+        let mut problems = self.problems();
+
+        problems.extend(self.check_paths());
+        problems.extend(self.check_certificates());
+
+        problems
+    }
+
+    /// Checks that do not touch the filesystem or network.
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        if !(1..=65535).contains(&self.server.port) {
+            problems.push(Problem::new("server.port", "must be between 1 and 65535"));
+        }
+        if let Err(e) = self.server.address.parse::<IpAddr>() {
+            problems.push(Problem::new("server.address", "not an IP address").with_source(e));
+        }
+        for origin in &self.server.cors_origins {
+            if let Err(e) = origin.parse::<axum::http::HeaderValue>() {
+                problems.push(
+                    Problem::new(
+                        "server.cors_origins",
+                        format!("invalid origin `{}`", origin),
+                    )
+                    .with_source(e),
+                );
+            }
+        }
+        if !(1..=65535).contains(&self.database.port) {
+            problems.push(Problem::new("database.port", "must be between 1 and 65535"));
+        }
+        for (key, value) in [
+            ("database.pool_size", self.database.pool_size as u64),
+            ("database.connect_timeout", self.database.connect_timeout),
+            ("database.checkout_timeout", self.database.checkout_timeout),
+            ("database.busy_timeout", self.database.busy_timeout),
+        ] {
+            if value == 0 {
+                problems.push(Problem::new(key, "must be positive"));
+            }
+        }
+        let engine = self
+            .database
+            .url
+            .as_deref()
+            .map_or(Engine::Postgres, Engine::from_url);
+        if !engine.is_enabled() {
+            problems.push(Problem::new(
+                "database.url",
+                format!(
+                    "{} support is not compiled in, enable the `{}` feature",
+                    engine.name(),
+                    engine.feature()
+                ),
+            ));
+        }
+        if !self.database.replicas.is_empty()
+            && std::iter::once(engine)
+                .chain(
+                    self.database
+                        .replicas
+                        .iter()
+                        .map(|url| Engine::from_url(url)),
+                )
+                .any(|engine| engine != Engine::Postgres)
+        {
+            problems.push(Problem::new(
+                "database.replicas",
+                "replicas are only supported with PostgreSQL",
+            ));
+        }
+        if !SSL_MODES.contains(&self.database.ssl_mode.as_str()) {
+            problems.push(Problem::new(
+                "database.ssl_mode",
+                format!("must be one of {}", SSL_MODES.join(", ")),
+            ));
+        }
+        if self.database.ssl_key.is_some() != self.database.ssl_cert.is_some() {
+            problems.push(Problem::new(
+                "database.ssl_key",
+                "must be set together with database.ssl_cert",
+            ));
+        }
+
+        if let Err(e) = parse_duration(&self.jwt.expires_in) {
+            problems.push(Problem::new("jwt.expires_in", e));
+        }
+        if self.jwt.maxage <= 0 {
+            problems.push(Problem::new("jwt.maxage", "must be positive"));
+        }
+        if let Some(weakness) = secret_weakness(&self.jwt.secret) {
+            // Keep the bundled defaults usable while developing.
+            if cfg!(debug_assertions) {
+                tracing::warn!("jwt.secret: {}", weakness);
+            } else {
+                problems.push(Problem::new("jwt.secret", weakness));
+            }
+        }
+
+        if self.storage.backend == StorageBackend::S3 && self.storage.s3.is_none() {
+            problems.push(Problem::new(
+                "storage.s3",
+                "required when storage.backend is \"s3\"",
+            ));
+        }
+        if self.storage.presigned_expires_in == 0 {
+            problems.push(Problem::new(
+                "storage.presigned_expires_in",
+                "must be positive",
+            ));
+        }
+
+        if self.uploads.chunk_max_size == 0 {
+            problems.push(Problem::new("uploads.chunk_max_size", "must be positive"));
+        }
+        if self.uploads.file_max_size as u64 > self.uploads.user_quota {
+            problems.push(Problem::new(
+                "uploads.file_max_size",
+                "exceeds uploads.user_quota",
+            ));
+        }
+
+        if !self.backup.interval.is_empty() {
+            if let Err(e) = parse_duration(&self.backup.interval) {
+                problems.push(Problem::new("backup.interval", e));
+            }
+        }
+        if self.backup.keep == 0 {
+            problems.push(Problem::new("backup.keep", "must be positive"));
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            problems.push(Problem::new("log.filter", "invalid filter directives").with_source(e));
+        }
+
+        problems
+    }
+
+    /// Creates the directories the server needs and checks they are writable.
+    fn check_paths(&self) -> Vec<Problem> {
+        let dirs = match self.paths.dirs() {
+            Ok(dirs) => dirs,
+            Err(e) => return vec![Problem::new("paths", "unavailable").with_source(e)],
+        };
+
+        // Uploads used to be stored directly in the data directory.
+        if let (Ok(data), Ok(uploads)) = (self.paths.data(), self.paths.uploads()) {
+            for name in ["avatars", "blobs"] {
+                let old = data.join(name);
+                if uploads != data && old.is_dir() && !uploads.join(name).exists() {
+                    tracing::warn!(
+                        "{} is not used anymore, move it into {}",
+                        old.display(),
+                        uploads.display()
+                    );
+                }
+            }
+        }
+
+        dirs.into_iter()
+            .filter_map(|dir| {
+                let problem = |e: std::io::Error| {
+                    Problem::new(dir.key, format!("{} is not writable", dir.path.display()))
+                        .with_source(e)
+                };
+
+                let probe = dir.path.join(".write-test");
+                dir.create()
+                    .and_then(|_| std::fs::write(&probe, b""))
+                    .and_then(|_| std::fs::remove_file(&probe))
+                    .map_err(problem)
+                    .err()
+            })
+            .collect()
+    }
+
+    fn check_certificates(&self) -> Vec<Problem> {
+        [
+            ("database.ssl_root_cert", &self.database.ssl_root_cert),
+            ("database.ssl_cert", &self.database.ssl_cert),
+            ("database.ssl_key", &self.database.ssl_key),
+        ]
+        .into_iter()
+        .filter_map(|(key, path)| {
+            let path = path.as_ref()?;
+            std::fs::File::open(path)
+                .map_err(|e| {
+                    Problem::new(key, format!("cannot read {}", path.display())).with_source(e)
+                })
+                .err()
+        })
+        .collect()
+    }
+
+    async fn check_database(&self) -> Result<(), Problem> {
+        let url = self.database_url();
+        let busy_timeout = Duration::from_secs(self.database.busy_timeout);
+
+        tokio::task::spawn_blocking(move || db::Connection::open(&url, busy_timeout).map(|_| ()))
+            .await
+            .map_err(|e| Problem::new("database", "connection check failed").with_source(e))?
+            .map_err(|e| Problem::new("database", "unreachable").with_source(e))
+    }
+}
+
+fn into_result(problems: Vec<Problem>) -> Result<(), ConfigError> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(problems))
+    }
+}
+
+/// Parses durations like `90s`, `60m`, `12h` or `7d`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+
+    let value = value
+        .parse::<u64>()
+        .map_err(|_| format!("`{}` is not a duration like `60m`", s))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit in `{}`, expected s, m, h or d", s)),
+    };
+
+    Ok(Duration::from_secs(value * seconds))
+}
+
+fn secret_weakness(secret: &str) -> Option<String> {
+    if secret == DEFAULT_SECRET {
+        Some(String::from("still set to the default value"))
+    } else if secret.len() < MIN_SECRET_LENGTH {
+        Some(format!("must be at least {} bytes long", MIN_SECRET_LENGTH))
+    } else if secret
+        .chars()
+        .collect::<std::collections::HashSet<_>>()
+        .len()
+        < 8
+    {
+        Some(String::from("has too few distinct characters"))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("60m"), Ok(Duration::from_secs(3600)));
+    assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(604800)));
+    assert!(parse_duration("60").is_err());
+    assert!(parse_duration("m").is_err());
+    assert!(parse_duration("1w").is_err());
+}
+
+#[test]
+fn test_problems() {
+    let mut config = Config::default();
+    config.jwt.secret = String::from("8mT2qXv9LrW4nZc7PbK1sHd6FgJ3yUe5");
+    if !Engine::Postgres.is_enabled() {
+        config.database.url = Some(String::from("sqlite://elnafo.db"));
+    }
+    assert!(config.problems().is_empty());
+
+    config.server.port = 70000;
+    config.server.address = String::from("localhost:80");
+    config.jwt.expires_in = String::from("soon");
+    config.storage.backend = StorageBackend::S3;
+    config.database.ssl_mode = String::from("strict");
+    config.database.busy_timeout = 0;
+
+    let keys = config
+        .problems()
+        .into_iter()
+        .map(|problem| problem.key)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        [
+            "server.port",
+            "server.address",
+            "database.busy_timeout",
+            "database.ssl_mode",
+            "jwt.expires_in",
+            "storage.s3"
+        ]
+    );
+}
@@ -0,0 +1,49 @@
+use crate::db::{schema::blobs, Connection};
+use diesel::prelude::*;
+
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Clone, Identifiable)]
+#[diesel(table_name = blobs)]
+#[diesel(primary_key(hash))]
+#[diesel(check_for_backend(crate::db::MultiBackend))]
+pub struct Blob {
+    pub hash: String,
+    pub size: i64,
+    pub ref_count: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Adds a reference to the blob, registering it if it is new.
+///
+/// Only content of a blob that was already referenced can be relied on: a collection
+/// may still be deleting what a blob registered anew left behind, so the caller holding
+/// the first reference writes the content again.
+pub fn acquire(conn: &mut Connection, hash: &str, size: i64) -> QueryResult<Blob> {
+    let upsert = diesel::insert_into(blobs::table)
+        .values((
+            blobs::hash.eq(hash),
+            blobs::size.eq(size),
+            blobs::ref_count.eq(1),
+        ))
+        .on_conflict(blobs::hash)
+        .do_update()
+        .set(blobs::ref_count.eq(blobs::ref_count + 1))
+        .returning(blobs::all_columns);
+
+    // Upserts are not part of the dialect shared by all engines.
+    match conn {
+        #[cfg(feature = "postgres")]
+        Connection::Postgres(conn) => upsert.get_result(conn),
+        #[cfg(feature = "sqlite")]
+        Connection::Sqlite(conn) => upsert.get_result(conn),
+    }
+}
+
+/// Drops a reference to the blob and returns the remaining count, if the blob is known.
+pub fn release(conn: &mut Connection, hash: &str) -> QueryResult<Option<i64>> {
+    diesel::update(blobs::table.find(hash))
+        .set(blobs::ref_count.eq(blobs::ref_count - 1))
+        .returning(blobs::ref_count)
+        .get_result(conn)
+        .optional()
+}
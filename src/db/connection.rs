@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
+use deadpool::Runtime;
+use deadpool_sync::SyncWrapper;
+use diesel::connection::{SimpleConnection, TransactionManager};
+use diesel::prelude::*;
+use diesel::ConnectionError;
+
+/// Database engine, chosen by the scheme of `database.url`: `sqlite://` for SQLite,
+/// anything else is handed to libpq.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    Postgres,
+    Sqlite,
+}
+
+impl Engine {
+    pub fn from_url(url: &str) -> Self {
+        match url.starts_with("sqlite:") {
+            true => Self::Sqlite,
+            false => Self::Postgres,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Postgres => "PostgreSQL",
+            Self::Sqlite => "SQLite",
+        }
+    }
+
+    /// Cargo feature compiling the engine in.
+    pub fn feature(&self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::Sqlite => "sqlite",
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Self::Postgres => cfg!(feature = "postgres"),
+            Self::Sqlite => cfg!(feature = "sqlite"),
+        }
+    }
+}
+
+/// Database file of a `sqlite://path` or `sqlite:path` URL.
+pub fn sqlite_path(url: &str) -> &str {
+    url.strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .unwrap_or(url)
+}
+
+/// A connection to any of the compiled in engines.
+///
+/// Queries written against it are shared by all engines; the few that need
+/// a dialect of their own (upserts, row locks) match on the variant.
+#[derive(diesel::MultiConnection)]
+pub enum AnyConnection {
+    #[cfg(feature = "postgres")]
+    Postgres(diesel::PgConnection),
+    #[cfg(feature = "sqlite")]
+    Sqlite(diesel::SqliteConnection),
+}
+
+impl AnyConnection {
+    /// Connects with the engine named by the scheme of `url`.
+    ///
+    /// SQLite connections wait up to `busy_timeout` for the write lock and enforce
+    /// foreign keys, which SQLite leaves off by default.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn open(url: &str, busy_timeout: Duration) -> ConnectionResult<Self> {
+        match Engine::from_url(url) {
+            #[cfg(feature = "postgres")]
+            Engine::Postgres => Ok(Self::Postgres(diesel::PgConnection::establish(url)?)),
+            #[cfg(feature = "sqlite")]
+            Engine::Sqlite => {
+                let mut connection = diesel::SqliteConnection::establish(sqlite_path(url))?;
+                connection
+                    .batch_execute(&format!(
+                        "PRAGMA busy_timeout = {}; PRAGMA foreign_keys = ON; \
+                         PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;",
+                        busy_timeout.as_millis()
+                    ))
+                    .map_err(ConnectionError::CouldntSetupConfiguration)?;
+
+                Ok(Self::Sqlite(connection))
+            }
+            #[allow(unreachable_patterns)]
+            engine => Err(ConnectionError::BadConnection(format!(
+                "{} support is not compiled in, enable the `{}` feature",
+                engine.name(),
+                engine.feature()
+            ))),
+        }
+    }
+
+    pub fn engine(&self) -> Engine {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::Postgres(_) => Engine::Postgres,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => Engine::Sqlite,
+        }
+    }
+}
+
+/// Opens pooled connections with [`AnyConnection::open`].
+#[derive(Debug)]
+pub struct Manager {
+    url: String,
+    busy_timeout: Duration,
+}
+
+impl Manager {
+    pub fn new(url: String, busy_timeout: Duration) -> Self {
+        Manager { url, busy_timeout }
+    }
+}
+
+impl managed::Manager for Manager {
+    type Type = SyncWrapper<AnyConnection>;
+    type Error = ConnectionError;
+
+    async fn create(&self) -> Result<Self::Type, Self::Error> {
+        let url = self.url.clone();
+        let busy_timeout = self.busy_timeout;
+
+        SyncWrapper::new(Runtime::Tokio1, move || {
+            AnyConnection::open(&url, busy_timeout)
+        })
+        .await
+    }
+
+    async fn recycle(
+        &self,
+        connection: &mut Self::Type,
+        _: &Metrics,
+    ) -> RecycleResult<Self::Error> {
+        if connection.is_mutex_poisoned() {
+            return Err(RecycleError::message("connection mutex is poisoned"));
+        }
+
+        connection
+            .interact(|connection| {
+                // Never hand out a connection stuck in a transaction someone failed to finish.
+                if <AnyConnection as diesel::Connection>::TransactionManager::is_broken_transaction_manager(
+                    connection,
+                ) {
+                    return Err(RecycleError::message("broken transaction"));
+                }
+
+                connection
+                    .batch_execute("SELECT 1")
+                    .map_err(|e| RecycleError::Message(e.to_string().into()))
+            })
+            .await
+            .map_err(|e| RecycleError::Message(e.to_string().into()))?
+    }
+}
@@ -0,0 +1,198 @@
+//! Logical copy of every table, loaded and stored through Diesel so it moves between engines.
+
+use diesel::prelude::*;
+
+use crate::db::{
+    blob::Blob,
+    file::File,
+    schema::{blobs, files, settings, settings_history, users},
+    setting::{Change, Setting},
+    types::{Id, Timestamp},
+    user::User,
+    Connection,
+};
+
+/// Runs the statement on the connection of the engine, where NULLs are bound with the type
+/// of their column; through the shared backend they are integers, which PostgreSQL only
+/// converts to text.
+macro_rules! execute {
+    ($conn:expr, $query:expr) => {
+        match $conn {
+            #[cfg(feature = "postgres")]
+            Connection::Postgres(conn) => $query.execute(conn),
+            #[cfg(feature = "sqlite")]
+            Connection::Sqlite(conn) => $query.execute(conn),
+        }
+    };
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Dump {
+    pub users: Vec<User>,
+    pub blobs: Vec<Blob>,
+    pub files: Vec<File>,
+    pub settings: Vec<Setting>,
+    pub settings_history: Vec<Change>,
+}
+
+impl Dump {
+    /// Loads every row. Should run inside a transaction to get a consistent snapshot.
+    pub fn read(conn: &mut Connection) -> QueryResult<Dump> {
+        Ok(Dump {
+            users: users::table
+                .order(users::id)
+                .select(users::all_columns)
+                .load(conn)?,
+            blobs: blobs::table
+                .order(blobs::hash)
+                .select(blobs::all_columns)
+                .load(conn)?,
+            files: files::table
+                .order(files::id)
+                .select(files::all_columns)
+                .load(conn)?,
+            settings: settings::table
+                .order(settings::key)
+                .select(settings::all_columns)
+                .load(conn)?,
+            settings_history: settings_history::table
+                .order(settings_history::id)
+                .select(settings_history::all_columns)
+                .load(conn)?,
+        })
+    }
+
+    /// Number of rows of every table.
+    pub fn counts(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("users", self.users.len()),
+            ("blobs", self.blobs.len()),
+            ("files", self.files.len()),
+            ("settings", self.settings.len()),
+            ("settings_history", self.settings_history.len()),
+        ]
+    }
+
+    /// Replaces the rows of every table with the dumped ones. Should run inside a transaction.
+    pub fn write(&self, conn: &mut Connection) -> QueryResult<()> {
+        clear(conn)?;
+
+        for user in &self.users {
+            execute!(
+                conn,
+                diesel::insert_into(users::table).values((
+                    users::id.eq(Id(user.id)),
+                    users::login.eq(&user.login),
+                    users::hashed_password.eq(&user.hashed_password),
+                    users::name.eq(&user.name),
+                    users::email.eq(&user.email),
+                    users::is_admin.eq(user.is_admin),
+                    users::avatar.eq(&user.avatar),
+                    users::avatar_style.eq(&user.avatar_style),
+                    users::created_at.eq(Timestamp(user.created_at)),
+                    users::updated_at.eq(Timestamp(user.updated_at)),
+                    users::last_login_at.eq(user.last_login_at.map(Timestamp)),
+                    users::last_seen_at.eq(user.last_seen_at.map(Timestamp)),
+                ))
+            )?;
+        }
+
+        for blob in &self.blobs {
+            execute!(
+                conn,
+                diesel::insert_into(blobs::table).values((
+                    blobs::hash.eq(&blob.hash),
+                    blobs::size.eq(blob.size),
+                    blobs::ref_count.eq(blob.ref_count),
+                    blobs::created_at.eq(Timestamp(blob.created_at)),
+                    blobs::updated_at.eq(Timestamp(blob.updated_at)),
+                ))
+            )?;
+        }
+
+        for file in &self.files {
+            execute!(
+                conn,
+                diesel::insert_into(files::table).values((
+                    files::id.eq(Id(file.id)),
+                    files::owner_id.eq(Id(file.owner_id)),
+                    files::name.eq(&file.name),
+                    files::mime.eq(&file.mime),
+                    files::size.eq(file.size),
+                    files::upload_offset.eq(file.upload_offset),
+                    files::hash.eq(&file.hash),
+                    files::visibility.eq(&file.visibility),
+                    files::created_at.eq(Timestamp(file.created_at)),
+                    files::updated_at.eq(Timestamp(file.updated_at)),
+                ))
+            )?;
+        }
+
+        for setting in &self.settings {
+            execute!(
+                conn,
+                diesel::insert_into(settings::table).values((
+                    settings::key.eq(&setting.key),
+                    settings::value.eq(&setting.value),
+                    settings::updated_at.eq(Timestamp(setting.updated_at)),
+                    settings::updated_by.eq(setting.updated_by.map(Id)),
+                ))
+            )?;
+        }
+
+        for change in &self.settings_history {
+            execute!(
+                conn,
+                diesel::insert_into(settings_history::table).values((
+                    settings_history::id.eq(change.id),
+                    settings_history::key.eq(&change.key),
+                    settings_history::old_value.eq(&change.old_value),
+                    settings_history::new_value.eq(&change.new_value),
+                    settings_history::changed_by.eq(change.changed_by.map(Id)),
+                    settings_history::changed_at.eq(Timestamp(change.changed_at)),
+                ))
+            )?;
+        }
+
+        match conn {
+            #[cfg(feature = "postgres")]
+            Connection::Postgres(conn) => {
+                // The sequence has to be moved past the restored ids.
+                diesel::sql_query(
+                    "SELECT setval(pg_get_serial_sequence('settings_history', 'id'), \
+                     COALESCE(MAX(id), 0) + 1, false) FROM settings_history",
+                )
+                .execute(conn)?;
+            }
+            // AUTOINCREMENT continues after the largest id by itself.
+            #[cfg(feature = "sqlite")]
+            Connection::Sqlite(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether no table has any row.
+pub fn is_empty(conn: &mut Connection) -> QueryResult<bool> {
+    let rows: [i64; 5] = [
+        users::table.count().get_result(conn)?,
+        blobs::table.count().get_result(conn)?,
+        files::table.count().get_result(conn)?,
+        settings::table.count().get_result(conn)?,
+        settings_history::table.count().get_result(conn)?,
+    ];
+
+    Ok(rows.iter().all(|count| *count == 0))
+}
+
+/// Deletes every row, referencing tables first.
+fn clear(conn: &mut Connection) -> QueryResult<()> {
+    diesel::delete(settings_history::table).execute(conn)?;
+    diesel::delete(settings::table).execute(conn)?;
+    diesel::delete(files::table).execute(conn)?;
+    diesel::delete(users::table).execute(conn)?;
+    diesel::delete(blobs::table).execute(conn)?;
+
+    Ok(())
+}
@@ -1,16 +1,21 @@
-use deadpool_diesel::postgres::PoolError;
+use deadpool::managed::PoolError;
 use deadpool_sync::InteractError;
 use diesel::result::Error as DieselError;
 use std::error::Error as StdError;
 use std::fmt::Display;
 
-#[derive(Debug, utoipa::ToSchema)]
+#[derive(Debug)]
 pub enum DatabaseError {
     Connection,
     Interaction(InteractError),
     Operation(DieselError),
     Query(DieselError),
-    Migration,
+    /// A migration failed, with its name and the cause.
+    Migration(String),
+    /// Migrations applied by a newer version, which this one cannot work with.
+    UnknownMigrations(Vec<String>),
+    /// Migrations to apply before starting, with `auto_migrate` disabled.
+    PendingMigrations(Vec<String>),
     Internal,
 }
 
@@ -20,17 +25,35 @@ impl Display for DatabaseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Self::Connection => write!(f, "Failed pool connection"),
-            Self::Interaction(ref e) => e.fmt(f),
+            Self::Interaction(InteractError::Panic(ref payload)) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown cause");
+                write!(f, "Database task panicked: {}", message)
+            }
+            Self::Interaction(ref e) => write!(f, "Database task failed: {}", e),
             Self::Operation(ref e) => e.fmt(f),
             Self::Query(ref e) => e.fmt(f),
-            Self::Migration => write!(f, "Failed to run migrations"),
+            Self::Migration(ref e) => write!(f, "Failed to run migrations: {}", e),
+            Self::UnknownMigrations(ref versions) => write!(
+                f,
+                "The database has migrations unknown to this version, it was upgraded by a newer one: {}",
+                versions.join(", ")
+            ),
+            Self::PendingMigrations(ref names) => write!(
+                f,
+                "Pending migrations, apply them with `migrate up`: {}",
+                names.join(", ")
+            ),
             Self::Internal => write!(f, "Internal error ..."),
         }
     }
 }
 
-impl From<PoolError> for DatabaseError {
-    fn from(_: PoolError) -> Self {
+impl From<PoolError<diesel::ConnectionError>> for DatabaseError {
+    fn from(_: PoolError<diesel::ConnectionError>) -> Self {
         Self::Connection
     }
 }
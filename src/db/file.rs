@@ -0,0 +1,78 @@
+use crate::db::{schema::files, types::Id, Connection};
+use diesel::prelude::*;
+
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Clone)]
+#[diesel(table_name = files)]
+#[diesel(check_for_backend(crate::db::MultiBackend))]
+pub struct File {
+    pub id: uuid::Uuid,
+    pub owner_id: uuid::Uuid,
+    pub name: String,
+    pub mime: String,
+    pub size: i64,
+    pub upload_offset: i64,
+    pub hash: Option<String>,
+    pub visibility: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Who can download a file: only its owner, anyone with the link, or anyone
+/// including listings on the owner's profile.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Private,
+    Link,
+    Public,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Private => "private",
+            Self::Link => "link",
+            Self::Public => "public",
+        }
+    }
+}
+
+impl std::str::FromStr for Visibility {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "private" => Ok(Self::Private),
+            "link" => Ok(Self::Link),
+            "public" => Ok(Self::Public),
+            _ => Err(()),
+        }
+    }
+}
+
+impl File {
+    pub fn visibility(&self) -> Visibility {
+        self.visibility.parse().unwrap_or(Visibility::Private)
+    }
+
+    /// Whether the user may download the file.
+    pub fn is_visible_to(&self, user_id: Option<uuid::Uuid>) -> bool {
+        self.visibility() != Visibility::Private || user_id == Some(self.owner_id)
+    }
+
+    /// Whether all the content has been received and stored as a blob.
+    pub fn is_complete(&self) -> bool {
+        self.hash.is_some()
+    }
+}
+
+/// Total size of the files owned by the user, including unfinished uploads.
+pub fn used_space(conn: &mut Connection, owner_id: uuid::Uuid) -> QueryResult<i64> {
+    // `SUM(BIGINT)` is `NUMERIC` in Postgres, cast it back to avoid pulling in bigdecimal.
+    files::table
+        .filter(files::owner_id.eq(Id(owner_id)))
+        .select(diesel::dsl::sql::<diesel::sql_types::BigInt>(
+            "CAST(COALESCE(SUM(size), 0) AS BIGINT)",
+        ))
+        .get_result(conn)
+}
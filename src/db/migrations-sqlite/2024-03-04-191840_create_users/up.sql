@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Version 4 UUIDs, as uuid_generate_v4() makes in PostgreSQL.
+CREATE TABLE "users"(
+	"id" TEXT NOT NULL PRIMARY KEY DEFAULT (lower(
+		hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-' ||
+		substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6))
+	)),
+	"login" TEXT NOT NULL,
+	"hashed_password" TEXT NOT NULL,
+	"name" TEXT NOT NULL,
+	"email" TEXT NOT NULL,
+	"is_admin" BOOLEAN NOT NULL CHECK ("is_admin" IN (0, 1)),
+	"avatar" TEXT NOT NULL
+);
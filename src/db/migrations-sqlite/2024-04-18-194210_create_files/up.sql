@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE "files"(
+	"id" TEXT NOT NULL PRIMARY KEY DEFAULT (lower(
+		hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-' ||
+		substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6))
+	)),
+	"owner_id" TEXT NOT NULL REFERENCES "users"("id") ON DELETE CASCADE,
+	"name" TEXT NOT NULL,
+	"mime" TEXT NOT NULL,
+	"size" BIGINT NOT NULL,
+	"upload_offset" BIGINT NOT NULL DEFAULT 0,
+	"hash" TEXT,
+	"visibility" TEXT NOT NULL DEFAULT 'private',
+	"created_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'))
+);
+
+CREATE INDEX "files_owner_id_idx" ON "files"("owner_id");
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE "settings"(
+	"key" TEXT NOT NULL PRIMARY KEY,
+	"value" TEXT NOT NULL,
+	"updated_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+	"updated_by" TEXT REFERENCES "users"("id") ON DELETE SET NULL
+);
+
+CREATE TABLE "settings_history"(
+	"id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	"key" TEXT NOT NULL,
+	"old_value" TEXT,
+	"new_value" TEXT,
+	"changed_by" TEXT REFERENCES "users"("id") ON DELETE SET NULL,
+	"changed_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'))
+);
+
+CREATE INDEX "settings_history_changed_at_idx" ON "settings_history"("changed_at");
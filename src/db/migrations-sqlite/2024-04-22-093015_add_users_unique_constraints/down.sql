@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS "users_email_key";
+DROP INDEX IF EXISTS "users_login_key";
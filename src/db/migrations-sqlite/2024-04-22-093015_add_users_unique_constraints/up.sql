@@ -0,0 +1,3 @@
+-- Your SQL goes here
+CREATE UNIQUE INDEX "users_login_key" ON "users"("login");
+CREATE UNIQUE INDEX "users_email_key" ON "users"("email");
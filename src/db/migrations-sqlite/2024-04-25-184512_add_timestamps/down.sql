@@ -0,0 +1,19 @@
+-- This file should undo anything in `up.sql`
+BEGIN;
+
+DROP TRIGGER "settings_set_updated_at";
+DROP TRIGGER "files_set_updated_at";
+DROP TRIGGER "blobs_set_updated_at";
+DROP TRIGGER "users_set_updated_at";
+
+ALTER TABLE "files" DROP COLUMN "updated_at";
+
+ALTER TABLE "blobs" DROP COLUMN "created_at";
+ALTER TABLE "blobs" DROP COLUMN "updated_at";
+
+ALTER TABLE "users" DROP COLUMN "created_at";
+ALTER TABLE "users" DROP COLUMN "updated_at";
+ALTER TABLE "users" DROP COLUMN "last_login_at";
+ALTER TABLE "users" DROP COLUMN "last_seen_at";
+
+COMMIT;
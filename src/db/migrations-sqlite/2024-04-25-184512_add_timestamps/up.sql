@@ -0,0 +1,91 @@
+-- Your SQL goes here
+-- SQLite cannot add columns defaulting to the current time, so the tables are rebuilt
+-- the way https://sqlite.org/lang_altertable.html#otheralter describes.
+PRAGMA foreign_keys = OFF;
+BEGIN;
+
+CREATE TABLE "users_new"(
+	"id" TEXT NOT NULL PRIMARY KEY DEFAULT (lower(
+		hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-' ||
+		substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6))
+	)),
+	"login" TEXT NOT NULL,
+	"hashed_password" TEXT NOT NULL,
+	"name" TEXT NOT NULL,
+	"email" TEXT NOT NULL,
+	"is_admin" BOOLEAN NOT NULL CHECK ("is_admin" IN (0, 1)),
+	"avatar" TEXT NOT NULL,
+	"avatar_style" TEXT NOT NULL DEFAULT 'identicon',
+	"created_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+	"updated_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+	"last_login_at" TEXT,
+	"last_seen_at" TEXT
+);
+INSERT INTO "users_new"("id", "login", "hashed_password", "name", "email", "is_admin", "avatar", "avatar_style")
+	SELECT "id", "login", "hashed_password", "name", "email", "is_admin", "avatar", "avatar_style" FROM "users";
+DROP TABLE "users";
+ALTER TABLE "users_new" RENAME TO "users";
+CREATE UNIQUE INDEX "users_login_key" ON "users"("login");
+CREATE UNIQUE INDEX "users_email_key" ON "users"("email");
+
+CREATE TABLE "blobs_new"(
+	"hash" TEXT NOT NULL PRIMARY KEY,
+	"size" BIGINT NOT NULL,
+	"ref_count" BIGINT NOT NULL DEFAULT 0,
+	"created_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+	"updated_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'))
+);
+INSERT INTO "blobs_new"("hash", "size", "ref_count") SELECT "hash", "size", "ref_count" FROM "blobs";
+DROP TABLE "blobs";
+ALTER TABLE "blobs_new" RENAME TO "blobs";
+
+CREATE TABLE "files_new"(
+	"id" TEXT NOT NULL PRIMARY KEY DEFAULT (lower(
+		hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-' ||
+		substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6))
+	)),
+	"owner_id" TEXT NOT NULL REFERENCES "users"("id") ON DELETE CASCADE,
+	"name" TEXT NOT NULL,
+	"mime" TEXT NOT NULL,
+	"size" BIGINT NOT NULL,
+	"upload_offset" BIGINT NOT NULL DEFAULT 0,
+	"hash" TEXT,
+	"visibility" TEXT NOT NULL DEFAULT 'private',
+	"created_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+	"updated_at" TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'))
+);
+INSERT INTO "files_new"("id", "owner_id", "name", "mime", "size", "upload_offset", "hash", "visibility", "created_at")
+	SELECT "id", "owner_id", "name", "mime", "size", "upload_offset", "hash", "visibility", "created_at" FROM "files";
+DROP TABLE "files";
+ALTER TABLE "files_new" RENAME TO "files";
+CREATE INDEX "files_owner_id_idx" ON "files"("owner_id");
+
+-- Like diesel_manage_updated_at() in PostgreSQL: bump `updated_at` unless the update set it.
+-- Logins and visits only touch the activity columns and leave `updated_at` alone.
+CREATE TRIGGER "users_set_updated_at" AFTER UPDATE OF
+	"login", "hashed_password", "name", "email", "is_admin", "avatar", "avatar_style" ON "users"
+	FOR EACH ROW WHEN NEW."updated_at" IS OLD."updated_at"
+BEGIN
+	UPDATE "users" SET "updated_at" = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') WHERE "id" = NEW."id";
+END;
+
+CREATE TRIGGER "blobs_set_updated_at" AFTER UPDATE ON "blobs"
+	FOR EACH ROW WHEN NEW."updated_at" IS OLD."updated_at"
+BEGIN
+	UPDATE "blobs" SET "updated_at" = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') WHERE "hash" = NEW."hash";
+END;
+
+CREATE TRIGGER "files_set_updated_at" AFTER UPDATE ON "files"
+	FOR EACH ROW WHEN NEW."updated_at" IS OLD."updated_at"
+BEGIN
+	UPDATE "files" SET "updated_at" = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') WHERE "id" = NEW."id";
+END;
+
+CREATE TRIGGER "settings_set_updated_at" AFTER UPDATE ON "settings"
+	FOR EACH ROW WHEN NEW."updated_at" IS OLD."updated_at"
+BEGIN
+	UPDATE "settings" SET "updated_at" = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') WHERE "key" = NEW."key";
+END;
+
+COMMIT;
+PRAGMA foreign_keys = ON;
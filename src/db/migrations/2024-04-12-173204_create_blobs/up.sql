@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE "blobs"(
+	"hash" TEXT NOT NULL PRIMARY KEY,
+	"size" BIGINT NOT NULL,
+	"ref_count" BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "users" ADD COLUMN "avatar_style" TEXT NOT NULL DEFAULT 'identicon';
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "files"(
+	"id" UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+	"owner_id" UUID NOT NULL REFERENCES "users"("id") ON DELETE CASCADE,
+	"name" TEXT NOT NULL,
+	"mime" TEXT NOT NULL,
+	"size" BIGINT NOT NULL,
+	"upload_offset" BIGINT NOT NULL DEFAULT 0,
+	"hash" TEXT,
+	"visibility" TEXT NOT NULL DEFAULT 'private',
+	"created_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "files_owner_id_idx" ON "files"("owner_id");
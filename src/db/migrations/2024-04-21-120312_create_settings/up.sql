@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE "settings"(
+	"key" TEXT NOT NULL PRIMARY KEY,
+	"value" TEXT NOT NULL,
+	"updated_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+	"updated_by" UUID REFERENCES "users"("id") ON DELETE SET NULL
+);
+
+CREATE TABLE "settings_history"(
+	"id" BIGSERIAL NOT NULL PRIMARY KEY,
+	"key" TEXT NOT NULL,
+	"old_value" TEXT,
+	"new_value" TEXT,
+	"changed_by" UUID REFERENCES "users"("id") ON DELETE SET NULL,
+	"changed_at" TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "settings_history_changed_at_idx" ON "settings_history"("changed_at");
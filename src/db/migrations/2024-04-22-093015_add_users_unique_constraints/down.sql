@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "users" DROP CONSTRAINT "users_email_key";
+ALTER TABLE "users" DROP CONSTRAINT "users_login_key";
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "users" ADD CONSTRAINT "users_login_key" UNIQUE ("login");
+ALTER TABLE "users" ADD CONSTRAINT "users_email_key" UNIQUE ("email");
@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER "set_updated_at" ON "settings";
+DROP TRIGGER "set_updated_at" ON "files";
+DROP TRIGGER "set_updated_at" ON "blobs";
+DROP TRIGGER "set_updated_at" ON "users";
+DROP FUNCTION users_set_updated_at();
+
+ALTER TABLE "files" DROP COLUMN "updated_at";
+
+ALTER TABLE "blobs"
+	DROP COLUMN "created_at",
+	DROP COLUMN "updated_at";
+
+ALTER TABLE "users"
+	DROP COLUMN "created_at",
+	DROP COLUMN "updated_at",
+	DROP COLUMN "last_login_at",
+	DROP COLUMN "last_seen_at";
@@ -0,0 +1,33 @@
+-- Your SQL goes here
+ALTER TABLE "users"
+	ADD COLUMN "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+	ADD COLUMN "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+	ADD COLUMN "last_login_at" TIMESTAMPTZ,
+	ADD COLUMN "last_seen_at" TIMESTAMPTZ;
+
+ALTER TABLE "blobs"
+	ADD COLUMN "created_at" TIMESTAMPTZ NOT NULL DEFAULT now(),
+	ADD COLUMN "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now();
+
+ALTER TABLE "files" ADD COLUMN "updated_at" TIMESTAMPTZ NOT NULL DEFAULT now();
+
+-- Logins and visits only touch the activity columns and leave `updated_at` alone.
+CREATE FUNCTION users_set_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF (
+        (NEW.login, NEW.hashed_password, NEW.name, NEW.email, NEW.is_admin, NEW.avatar, NEW.avatar_style)
+            IS DISTINCT FROM
+        (OLD.login, OLD.hashed_password, OLD.name, OLD.email, OLD.is_admin, OLD.avatar, OLD.avatar_style) AND
+        NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+    ) THEN
+        NEW.updated_at := current_timestamp;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "set_updated_at" BEFORE UPDATE ON "users"
+    FOR EACH ROW EXECUTE PROCEDURE users_set_updated_at();
+SELECT diesel_manage_updated_at('blobs');
+SELECT diesel_manage_updated_at('files');
+SELECT diesel_manage_updated_at('settings');
@@ -1,29 +1,175 @@
+pub mod blob;
+pub mod connection;
+pub mod dump;
 pub mod errors;
+pub mod file;
+pub mod replica;
 pub mod schema;
+pub mod setting;
+pub mod types;
 pub mod user;
 
-use deadpool_diesel::postgres::Manager;
-pub use deadpool_diesel::postgres::Pool;
-use diesel::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use diesel::connection::{AnsiTransactionManager, TransactionManager};
+use diesel::migration::{Migration, MigrationSource, MigrationVersion};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
+use crate::config::Config;
+pub use connection::{
+    sqlite_path, AnyConnection as Connection, Engine, Manager, MultiBackend, MultiRawValue,
+};
 use errors::DatabaseError;
+use replica::{Replica, Replicas};
+
+#[cfg(not(any(feature = "postgres", feature = "sqlite")))]
+compile_error!("at least one of the `postgres` and `sqlite` features is required");
+
+pub type Connections = deadpool::managed::Pool<Manager>;
+
+/// Connections to the primary database and its read replicas.
+#[derive(Clone)]
+pub struct Pool {
+    pub primary: Connections,
+    pub replicas: Arc<Replicas>,
+}
 
-pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/migrations/");
+impl Pool {
+    /// Usage of the primary connections.
+    pub fn status(&self) -> deadpool::Status {
+        self.primary.status()
+    }
+}
+
+#[cfg(feature = "postgres")]
+pub const POSTGRES_MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/migrations/");
+#[cfg(feature = "sqlite")]
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/migrations-sqlite/");
+
+/// Longest pause between connection attempts at startup.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
 
-pub fn create_pool(database_url: String) -> Pool {
-    let manager = Manager::new(database_url, deadpool_diesel::Runtime::Tokio1);
+/// Times a transaction is attempted before a serialization failure is returned.
+const TRANSACTION_ATTEMPTS: u32 = 5;
 
-    Pool::builder(manager).build().unwrap()
+/// Isolation level of a transaction, see the PostgreSQL manual on transaction isolation.
+///
+/// SQLite has a single writer, so there every transaction takes the write lock when it
+/// begins and runs as if serializable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Isolation {
+    /// Every statement sees the data committed before it began.
+    ReadCommitted,
+    /// Every statement sees the data committed before the transaction began.
+    RepeatableRead,
+    /// As if the transactions ran one after another; reads that decide on writes
+    /// (count, then insert) need this to be safe against concurrent requests.
+    Serializable,
 }
 
+/// Builds the pools from the `[database]` section, without connecting yet.
+pub fn create_pool(config: &Config) -> Result<Pool, DatabaseError> {
+    let replicas = config
+        .replica_urls()
+        .into_iter()
+        .map(|url| connections(config, url).map(Replica::new))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Pool {
+        primary: connections(config, config.database_url())?,
+        replicas: Arc::new(Replicas::new(
+            replicas,
+            Duration::from_secs(config.database.read_your_writes),
+        )),
+    })
+}
+
+fn connections(config: &Config, url: String) -> Result<Connections, DatabaseError> {
+    let database = &config.database;
+    let manager = Manager::new(url, Duration::from_secs(database.busy_timeout));
+    let connect_timeout = Some(Duration::from_secs(database.connect_timeout));
+
+    Connections::builder(manager)
+        .max_size(database.pool_size)
+        .wait_timeout(Some(Duration::from_secs(database.checkout_timeout)))
+        .create_timeout(connect_timeout)
+        .recycle_timeout(connect_timeout)
+        .runtime(deadpool::Runtime::Tokio1)
+        .build()
+        .map_err(|_| DatabaseError::Internal)
+}
+
+/// Builds the pools and waits for the primary database to accept connections, retrying
+/// with exponential backoff for up to `database.startup_timeout` seconds.
+pub async fn connect(config: &Config) -> Result<Pool, DatabaseError> {
+    let pool = create_pool(config)?;
+    let deadline = Instant::now() + Duration::from_secs(config.database.startup_timeout);
+    let mut delay = Duration::from_millis(500);
+
+    loop {
+        match pool.primary.get().await {
+            Ok(_) => return Ok(pool),
+            Err(e) if Instant::now() + delay < deadline => {
+                tracing::warn!("Database unavailable, retrying in {:?}: {}", delay, e);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(e) => {
+                tracing::error!("Database unavailable: {}", e);
+                return Err(DatabaseError::Connection);
+            }
+        }
+    }
+}
+
+/// Runs `f` on the primary database.
 pub async fn execute<F, T>(pool: &Pool, f: F) -> Result<T, DatabaseError>
 where
-    F: FnOnce(&mut PgConnection) -> Result<T, diesel::result::Error> + Send + 'static,
+    F: FnOnce(&mut Connection) -> Result<T, diesel::result::Error> + Send + 'static,
     T: Send + 'static,
 {
-    let connection = pool.get().await.map_err(|_| DatabaseError::Connection)?;
+    let connection = pool
+        .primary
+        .get()
+        .await
+        .map_err(|_| DatabaseError::Connection)?;
 
+    interact(connection, f).await
+}
+
+/// Runs the lookup `f` on a read replica, or on the primary when there are no healthy
+/// replicas or `reader` changed something within the read-your-writes window.
+///
+/// Replicas lag behind the primary, so reads that decide on writes belong in [`execute`].
+pub async fn execute_read<F, T>(
+    pool: &Pool,
+    reader: Option<uuid::Uuid>,
+    f: F,
+) -> Result<T, DatabaseError>
+where
+    F: FnOnce(&mut Connection) -> Result<T, diesel::result::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let replica = match reader {
+        Some(reader) if pool.replicas.wrote_recently(reader) => None,
+        _ => pool.replicas.get().await,
+    };
+
+    match replica {
+        Some(connection) => interact(connection, f).await,
+        None => execute(pool, f).await,
+    }
+}
+
+async fn interact<F, T>(
+    connection: deadpool::managed::Object<Manager>,
+    f: F,
+) -> Result<T, DatabaseError>
+where
+    F: FnOnce(&mut Connection) -> Result<T, diesel::result::Error> + Send + 'static,
+    T: Send + 'static,
+{
     connection
         .interact(move |connection| f(connection))
         .await
@@ -31,12 +177,224 @@ where
         .map_err(DatabaseError::Query)
 }
 
-pub async fn run_migrations(pool: &Pool) -> Result<(), DatabaseError> {
+/// Runs `f` inside a transaction, committing when it succeeds and rolling back otherwise.
+///
+/// Transactions failing to serialize with concurrent ones are retried from the start,
+/// so `f` may run several times.
+pub async fn transaction<F, T>(pool: &Pool, isolation: Isolation, f: F) -> Result<T, DatabaseError>
+where
+    F: Fn(&mut Connection) -> Result<T, diesel::result::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    use diesel::result::{DatabaseErrorKind, Error};
+
     execute(pool, move |connection| {
-        Ok(connection
-            .run_pending_migrations(MIGRATIONS)
-            .map(|_| ())
-            .map_err(|_| DatabaseError::Migration))
+        let mut attempt = 1;
+
+        loop {
+            match run_transaction(connection, isolation, &f) {
+                Err(Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _))
+                    if attempt < TRANSACTION_ATTEMPTS =>
+                {
+                    std::thread::sleep(Duration::from_millis(10 * attempt as u64));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     })
-    .await?
+    .await
+}
+
+/// Same as `Connection::transaction`, but beginning with the statement for the engine and isolation.
+#[cfg_attr(not(feature = "postgres"), allow(unused_variables))]
+fn run_transaction<F, T>(
+    connection: &mut Connection,
+    isolation: Isolation,
+    f: F,
+) -> Result<T, diesel::result::Error>
+where
+    F: Fn(&mut Connection) -> Result<T, diesel::result::Error>,
+{
+    type Manager = <Connection as diesel::Connection>::TransactionManager;
+
+    match connection {
+        #[cfg(feature = "postgres")]
+        Connection::Postgres(connection) => AnsiTransactionManager::begin_transaction_sql(
+            connection,
+            match isolation {
+                Isolation::ReadCommitted => "BEGIN TRANSACTION ISOLATION LEVEL READ COMMITTED",
+                Isolation::RepeatableRead => "BEGIN TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+                Isolation::Serializable => "BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+            },
+        )?,
+        #[cfg(feature = "sqlite")]
+        Connection::Sqlite(connection) => {
+            AnsiTransactionManager::begin_transaction_sql(connection, "BEGIN IMMEDIATE")?
+        }
+    }
+
+    match f(connection) {
+        Ok(value) => {
+            Manager::commit_transaction(connection)?;
+            Ok(value)
+        }
+        Err(e) => match Manager::rollback_transaction(connection) {
+            Ok(()) | Err(diesel::result::Error::BrokenTransactionManager) => Err(e),
+            Err(rollback) => Err(rollback),
+        },
+    }
+}
+
+/// Migrations of the engine the connection uses.
+fn migrations(connection: &Connection) -> EmbeddedMigrations {
+    match connection {
+        #[cfg(feature = "postgres")]
+        Connection::Postgres(_) => POSTGRES_MIGRATIONS,
+        #[cfg(feature = "sqlite")]
+        Connection::Sqlite(_) => SQLITE_MIGRATIONS,
+    }
+}
+
+/// State of a migration in the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrationState {
+    Pending,
+    Applied,
+    /// Applied by a newer version, this one does not know it.
+    Unknown,
+}
+
+type Migrations = Vec<Box<dyn Migration<MultiBackend>>>;
+
+/// Migrations known for the engine, ordered by version, and the versions applied to the database.
+fn migration_versions(
+    connection: &mut Connection,
+) -> Result<(Migrations, Vec<MigrationVersion<'static>>), DatabaseError> {
+    let source = migrations(connection);
+    let mut known = MigrationSource::<MultiBackend>::migrations(&source)
+        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+    known.sort_by(|a, b| a.name().version().cmp(&b.name().version()));
+    let applied = connection
+        .applied_migrations()
+        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+
+    Ok((known, applied))
+}
+
+/// Applied versions missing from the known migrations.
+fn unknown_versions(known: &Migrations, applied: &[MigrationVersion<'static>]) -> Vec<String> {
+    applied
+        .iter()
+        .filter(|version| !known.iter().any(|m| &m.name().version() == *version))
+        .map(|version| version.to_string())
+        .collect()
+}
+
+/// Applies pending migrations one by one and returns their names.
+///
+/// Refuses to touch a database already migrated by a newer version.
+pub async fn run_migrations(pool: &Pool) -> Result<Vec<String>, DatabaseError> {
+    execute(pool, |connection| Ok(apply_pending(connection))).await?
+}
+
+fn apply_pending(connection: &mut Connection) -> Result<Vec<String>, DatabaseError> {
+    let (known, applied) = migration_versions(connection)?;
+    let unknown = unknown_versions(&known, &applied);
+    if !unknown.is_empty() {
+        return Err(DatabaseError::UnknownMigrations(unknown));
+    }
+
+    let mut names = Vec::new();
+    for migration in known
+        .iter()
+        .filter(|m| !applied.contains(&m.name().version()))
+    {
+        let name = migration.name().to_string();
+        let started = Instant::now();
+        connection
+            .run_migration(migration)
+            .map_err(|e| DatabaseError::Migration(format!("{}: {}", name, e)))?;
+        tracing::info!("Applied migration {} in {:?}", name, started.elapsed());
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+/// Reverts up to `steps` of the last applied migrations and returns their names.
+pub async fn revert_migrations(pool: &Pool, steps: usize) -> Result<Vec<String>, DatabaseError> {
+    execute(pool, move |connection| Ok(revert_last(connection, steps))).await?
+}
+
+fn revert_last(connection: &mut Connection, steps: usize) -> Result<Vec<String>, DatabaseError> {
+    let (known, applied) = migration_versions(connection)?;
+
+    let mut names = Vec::new();
+    // Applied versions come latest first.
+    for version in applied.iter().take(steps) {
+        let migration = known
+            .iter()
+            .find(|m| &m.name().version() == version)
+            .ok_or_else(|| DatabaseError::UnknownMigrations(vec![version.to_string()]))?;
+
+        let name = migration.name().to_string();
+        let started = Instant::now();
+        connection
+            .revert_migration(migration)
+            .map_err(|e| DatabaseError::Migration(format!("{}: {}", name, e)))?;
+        tracing::info!("Reverted migration {} in {:?}", name, started.elapsed());
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+/// Every migration by name with its state, followed by the versions applied by a newer version.
+pub async fn migration_status(pool: &Pool) -> Result<Vec<(String, MigrationState)>, DatabaseError> {
+    execute(pool, |connection| Ok(status(connection))).await?
+}
+
+fn status(connection: &mut Connection) -> Result<Vec<(String, MigrationState)>, DatabaseError> {
+    let (known, applied) = migration_versions(connection)?;
+    let unknown = unknown_versions(&known, &applied);
+
+    Ok(known
+        .iter()
+        .map(|migration| {
+            let name = migration.name();
+            let state = match applied.contains(&name.version()) {
+                true => MigrationState::Applied,
+                false => MigrationState::Pending,
+            };
+            (name.to_string(), state)
+        })
+        .chain(
+            unknown
+                .into_iter()
+                .map(|version| (version, MigrationState::Unknown)),
+        )
+        .collect())
+}
+
+/// Names of the migrations still to apply.
+///
+/// Fails like [`run_migrations`] if the database was migrated by a newer version.
+pub async fn pending_migrations(pool: &Pool) -> Result<Vec<String>, DatabaseError> {
+    let status = migration_status(pool).await?;
+
+    let unknown = status
+        .iter()
+        .filter(|(_, state)| *state == MigrationState::Unknown)
+        .map(|(version, _)| version.to_owned())
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(DatabaseError::UnknownMigrations(unknown));
+    }
+
+    Ok(status
+        .into_iter()
+        .filter(|(_, state)| *state == MigrationState::Pending)
+        .map(|(name, _)| name)
+        .collect())
 }
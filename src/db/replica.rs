@@ -0,0 +1,129 @@
+//! Read replicas serving lookups in place of the primary database.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{Connections, Manager};
+
+/// How long a replica that failed to connect is left alone before it is tried again.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct Replica {
+    pub connections: Connections,
+    /// Until when the replica is skipped after failing to connect.
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    pub fn new(connections: Connections) -> Self {
+        Replica {
+            connections,
+            down_until: Mutex::new(None),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.down_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn mark_down(&self) {
+        *self.down_until.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(Instant::now() + RETRY_INTERVAL);
+    }
+}
+
+/// The replicas, used in turn, and who must still read from the primary.
+pub struct Replicas {
+    pub replicas: Vec<Replica>,
+    next: AtomicUsize,
+    /// How long after a change its author reads from the primary.
+    window: Duration,
+    /// When users last changed something.
+    writes: Mutex<HashMap<uuid::Uuid, Instant>>,
+}
+
+impl Replicas {
+    pub fn new(replicas: Vec<Replica>, window: Duration) -> Self {
+        Replicas {
+            replicas,
+            next: AtomicUsize::new(0),
+            window,
+            writes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records that the user changed something, sending their lookups to the primary
+    /// until the replicas have caught up.
+    pub fn record_write(&self, user: uuid::Uuid) {
+        if self.replicas.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut writes = self.writes.lock().unwrap_or_else(|e| e.into_inner());
+        writes.retain(|_, at| now.duration_since(*at) < self.window);
+        writes.insert(user, now);
+    }
+
+    /// Whether the user changed something too recently to read from a replica.
+    pub fn wrote_recently(&self, user: uuid::Uuid) -> bool {
+        self.writes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&user)
+            .is_some_and(|at| at.elapsed() < self.window)
+    }
+
+    /// A connection to the next healthy replica, or `None` to use the primary.
+    ///
+    /// Replicas failing to connect are skipped for [`RETRY_INTERVAL`].
+    pub async fn get(&self) -> Option<deadpool::managed::Object<Manager>> {
+        let count = self.replicas.len();
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+
+        for offset in 0..count {
+            let replica = &self.replicas[(first + offset) % count];
+            if !replica.is_healthy() {
+                continue;
+            }
+
+            match replica.connections.get().await {
+                Ok(connection) => return Some(connection),
+                Err(e) => {
+                    replica.mark_down();
+                    tracing::warn!(
+                        "Read replica unavailable, skipping it for {:?}: {}",
+                        RETRY_INTERVAL,
+                        e
+                    );
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[tokio::test]
+async fn test_replicas() {
+    let mut config = crate::config::Config::default();
+    // Nothing listens there, so the replica fails to connect.
+    config.database.replicas = vec![String::from("postgres://elnafo@127.0.0.1:1/elnafo")];
+    config.database.connect_timeout = 1;
+    config.database.read_your_writes = 60;
+    let pool = super::create_pool(&config).unwrap();
+
+    let user = uuid::Uuid::new_v4();
+    assert!(!pool.replicas.wrote_recently(user));
+    pool.replicas.record_write(user);
+    assert!(pool.replicas.wrote_recently(user));
+
+    assert!(pool.replicas.replicas[0].is_healthy());
+    assert!(pool.replicas.get().await.is_none());
+    assert!(!pool.replicas.replicas[0].is_healthy());
+}
@@ -1,6 +1,9 @@
 // @generated automatically by Diesel CLI.
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::{Timestamptz, Uuid};
+
     users (id) {
         id -> Uuid,
         login -> Text,
@@ -9,5 +12,73 @@ diesel::table! {
         email -> Text,
         is_admin -> Bool,
         avatar -> Text,
+        avatar_style -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_login_at -> Nullable<Timestamptz>,
+        last_seen_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::{Timestamptz, Uuid};
+
+    blobs (hash) {
+        hash -> Text,
+        size -> Int8,
+        ref_count -> Int8,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::{Timestamptz, Uuid};
+
+    files (id) {
+        id -> Uuid,
+        owner_id -> Uuid,
+        name -> Text,
+        mime -> Text,
+        size -> Int8,
+        upload_offset -> Int8,
+        hash -> Nullable<Text>,
+        visibility -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::{Timestamptz, Uuid};
+
+    settings (key) {
+        key -> Text,
+        value -> Text,
+        updated_at -> Timestamptz,
+        updated_by -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::{Timestamptz, Uuid};
+
+    settings_history (id) {
+        id -> Int8,
+        key -> Text,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+        changed_by -> Nullable<Uuid>,
+        changed_at -> Timestamptz,
+    }
+}
+
+diesel::joinable!(files -> users (owner_id));
+diesel::joinable!(settings -> users (updated_by));
+diesel::joinable!(settings_history -> users (changed_by));
+
+diesel::allow_tables_to_appear_in_same_query!(blobs, files, settings, settings_history, users,);
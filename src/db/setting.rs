@@ -0,0 +1,106 @@
+use crate::db::{
+    schema::{settings, settings_history},
+    types::{Id, Timestamp},
+    Connection,
+};
+use diesel::prelude::*;
+
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Clone, Identifiable)]
+#[diesel(table_name = settings)]
+#[diesel(primary_key(key))]
+#[diesel(check_for_backend(crate::db::MultiBackend))]
+pub struct Setting {
+    pub key: String,
+    /// JSON encoded value.
+    pub value: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub updated_by: Option<uuid::Uuid>,
+}
+
+/// A past change of a setting, `None` values meaning the default was in effect.
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Clone)]
+#[diesel(table_name = settings_history)]
+#[diesel(check_for_backend(crate::db::MultiBackend))]
+pub struct Change {
+    pub id: i64,
+    pub key: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_by: Option<uuid::Uuid>,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub fn all(conn: &mut Connection) -> QueryResult<Vec<Setting>> {
+    settings::table.select(Setting::as_select()).load(conn)
+}
+
+/// Most recent changes first.
+pub fn history(conn: &mut Connection, limit: i64) -> QueryResult<Vec<Change>> {
+    settings_history::table
+        .order(settings_history::id.desc())
+        .limit(limit)
+        .select(Change::as_select())
+        .load(conn)
+}
+
+/// Stores the setting, or removes it to restore the default when `value` is `None`,
+/// and records the change. Should run inside a transaction.
+pub fn set(
+    conn: &mut Connection,
+    key: &str,
+    value: Option<String>,
+    user_id: Option<uuid::Uuid>,
+) -> QueryResult<()> {
+    let current = settings::table.find(key).select(settings::value);
+    let old_value = match conn {
+        #[cfg(feature = "postgres")]
+        Connection::Postgres(conn) => current.for_update().first::<String>(conn),
+        // The transaction already holds the write lock of the whole database.
+        #[cfg(feature = "sqlite")]
+        Connection::Sqlite(conn) => current.first::<String>(conn),
+    }
+    .optional()?;
+
+    if old_value == value {
+        return Ok(());
+    }
+
+    match &value {
+        Some(value) => {
+            let upsert = diesel::insert_into(settings::table)
+                .values((
+                    settings::key.eq(key),
+                    settings::value.eq(value),
+                    settings::updated_by.eq(user_id.map(Id)),
+                ))
+                .on_conflict(settings::key)
+                .do_update()
+                .set((
+                    settings::value.eq(value),
+                    settings::updated_at.eq(Timestamp::now()),
+                    settings::updated_by.eq(user_id.map(Id)),
+                ));
+
+            match conn {
+                #[cfg(feature = "postgres")]
+                Connection::Postgres(conn) => upsert.execute(conn)?,
+                #[cfg(feature = "sqlite")]
+                Connection::Sqlite(conn) => upsert.execute(conn)?,
+            };
+        }
+        None => {
+            diesel::delete(settings::table.find(key)).execute(conn)?;
+        }
+    }
+
+    diesel::insert_into(settings_history::table)
+        .values((
+            settings_history::key.eq(key),
+            settings_history::old_value.eq(old_value),
+            settings_history::new_value.eq(value),
+            settings_history::changed_by.eq(user_id.map(Id)),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! UUID and timestamp types for every engine: native in PostgreSQL, `TEXT` in SQLite.
+//!
+//! Columns of these types load straight into `uuid::Uuid` and `chrono::DateTime<Utc>`,
+//! but values bound in queries have to be wrapped in [`Id`] or [`Timestamp`].
+
+use diesel::deserialize::{self, FromSql};
+use diesel::expression::AsExpression;
+use diesel::query_builder::QueryId;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{self, ops, HasSqlType, Interval, SqlType};
+
+use super::{MultiBackend, MultiRawValue};
+
+/// `UUID` in PostgreSQL, hyphenated lowercase `TEXT` in SQLite.
+#[derive(Debug, Clone, Copy, Default, SqlType, QueryId)]
+#[diesel(postgres_type(oid = 2950, array_oid = 2951))]
+#[diesel(sqlite_type(name = "Text"))]
+pub struct Uuid;
+
+/// `TIMESTAMPTZ` in PostgreSQL, `TEXT` like `2024-04-22 09:30:15.123+00:00` in SQLite.
+#[derive(Debug, Clone, Copy, Default, SqlType, QueryId)]
+#[diesel(postgres_type(oid = 1184, array_oid = 1185))]
+#[diesel(sqlite_type(name = "Text"))]
+pub struct Timestamptz;
+
+// `table!` gives timestamp columns interval arithmetic, as for the built-in types.
+impl ops::Add for Timestamptz {
+    type Rhs = Interval;
+    type Output = Timestamptz;
+}
+
+impl ops::Sub for Timestamptz {
+    type Rhs = Interval;
+    type Output = Timestamptz;
+}
+
+/// A UUID bound in a query, e.g. `users::table.find(Id(id))`.
+#[derive(Debug, Clone, Copy, PartialEq, AsExpression)]
+#[diesel(sql_type = Uuid)]
+pub struct Id(pub uuid::Uuid);
+
+impl From<uuid::Uuid> for Id {
+    fn from(id: uuid::Uuid) -> Self {
+        Id(id)
+    }
+}
+
+/// A point in time bound in a query.
+#[derive(Debug, Clone, Copy, PartialEq, AsExpression)]
+#[diesel(sql_type = Timestamptz)]
+pub struct Timestamp(pub chrono::DateTime<chrono::Utc>);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(chrono::Utc::now())
+    }
+}
+
+#[cfg(feature = "postgres")]
+mod postgres {
+    use diesel::pg::{Pg, PgValue};
+
+    use super::*;
+
+    impl ToSql<Uuid, Pg> for Id {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+            ToSql::<sql_types::Uuid, Pg>::to_sql(&self.0, out)
+        }
+    }
+
+    impl FromSql<Uuid, Pg> for uuid::Uuid {
+        fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+            FromSql::<sql_types::Uuid, Pg>::from_sql(value)
+        }
+    }
+
+    impl ToSql<Timestamptz, Pg> for Timestamp {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+            ToSql::<sql_types::Timestamptz, Pg>::to_sql(&self.0, out)
+        }
+    }
+
+    impl FromSql<Timestamptz, Pg> for chrono::DateTime<chrono::Utc> {
+        fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+            FromSql::<sql_types::Timestamptz, Pg>::from_sql(value)
+        }
+    }
+}
+
+// diesel only makes `uuid::Uuid` queryable as part of its PostgreSQL support.
+#[cfg(not(feature = "postgres"))]
+impl diesel::Queryable<Uuid, MultiBackend> for uuid::Uuid {
+    type Row = Self;
+
+    fn build(row: Self::Row) -> deserialize::Result<Self> {
+        Ok(row)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use diesel::sqlite::{Sqlite, SqliteValue};
+
+    use super::*;
+
+    impl ToSql<Uuid, Sqlite> for Id {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+            out.set_value(self.0.hyphenated().to_string());
+            Ok(IsNull::No)
+        }
+    }
+
+    impl FromSql<Uuid, Sqlite> for uuid::Uuid {
+        fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+            let text = <String as FromSql<sql_types::Text, Sqlite>>::from_sql(value)?;
+            Ok(text.parse()?)
+        }
+    }
+
+    impl ToSql<Timestamptz, Sqlite> for Timestamp {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+            ToSql::<sql_types::TimestamptzSqlite, Sqlite>::to_sql(&self.0, out)
+        }
+    }
+
+    impl FromSql<Timestamptz, Sqlite> for chrono::DateTime<chrono::Utc> {
+        fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+            FromSql::<sql_types::TimestamptzSqlite, Sqlite>::from_sql(value)
+        }
+    }
+}
+
+/// Binds and loads the type with whichever engine the connection uses, the way the
+/// derived `MultiConnection` does for the types every engine has.
+macro_rules! multi_backend {
+    ($sql_type:ident, $bound:ty => $loaded:ty) => {
+        impl HasSqlType<$sql_type> for MultiBackend {
+            fn metadata(lookup: &mut Self::MetadataLookup) -> Self::TypeMetadata {
+                MultiBackend::lookup_sql_type::<$sql_type>(lookup)
+            }
+        }
+
+        impl ToSql<$sql_type, MultiBackend> for $bound {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, MultiBackend>) -> serialize::Result {
+                out.set_value(($sql_type, self));
+                Ok(IsNull::No)
+            }
+        }
+
+        impl FromSql<$sql_type, MultiBackend> for $loaded {
+            fn from_sql(value: MultiRawValue<'_>) -> deserialize::Result<Self> {
+                value.from_sql::<Self, $sql_type>()
+            }
+        }
+    };
+}
+
+multi_backend!(Uuid, Id => uuid::Uuid);
+multi_backend!(Timestamptz, Timestamp => chrono::DateTime<chrono::Utc>);
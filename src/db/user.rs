@@ -1,13 +1,14 @@
-use crate::db::schema::users;
+use crate::db::{schema::users, MultiBackend};
 use diesel::{
     dsl::{AsSelect, SqlTypeOf},
-    pg::Pg,
     prelude::*,
 };
 
-#[derive(serde::Serialize, Queryable, Selectable, Clone, Identifiable, AsChangeset)]
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, Queryable, Selectable, Clone, Identifiable,
+)]
 #[diesel(table_name = users)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(crate::db::MultiBackend))]
 pub struct User {
     pub id: uuid::Uuid,
     pub login: String,
@@ -16,10 +17,17 @@ pub struct User {
     pub email: String,
     pub is_admin: bool,
     pub avatar: String,
+    pub avatar_style: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Last change to the profile; logins and visits do not count.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub last_login_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last authenticated request, recorded at most every
+    /// [`LAST_SEEN_INTERVAL`](crate::state::LAST_SEEN_INTERVAL).
+    pub last_seen_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
-#[derive(serde::Deserialize, Insertable)]
-#[diesel(table_name = users)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct NewUser {
     pub login: String,
     pub hashed_password: String,
@@ -27,10 +35,32 @@ pub struct NewUser {
     pub email: String,
     pub is_admin: bool,
     pub avatar: String,
+    pub avatar_style: String,
+}
+
+/// Columns to change, `None` leaving a column as it is.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = users)]
+pub struct UserChanges {
+    pub hashed_password: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub is_admin: Option<bool>,
+    pub avatar_style: Option<String>,
+}
+
+impl UserChanges {
+    pub fn is_empty(&self) -> bool {
+        self.hashed_password.is_none()
+            && self.name.is_none()
+            && self.email.is_none()
+            && self.is_admin.is_none()
+            && self.avatar_style.is_none()
+    }
 }
 
 #[allow(dead_code)]
-type SqlType = SqlTypeOf<AsSelect<User, Pg>>;
+type SqlType = SqlTypeOf<AsSelect<User, MultiBackend>>;
 
 #[allow(dead_code)]
-type BoxedQuery<'a> = users::BoxedQuery<'a, Pg, SqlType>;
+type BoxedQuery<'a> = users::BoxedQuery<'a, MultiBackend, SqlType>;
@@ -1,41 +1,118 @@
 pub mod api;
+pub mod backup;
+pub mod cli;
 pub mod config;
 pub mod db;
+pub mod repository;
 pub mod resources;
+pub mod settings;
 pub mod state;
+pub mod storage;
 
-use axum::{http::Uri, response::IntoResponse, routing::get, Router};
+use arc_swap::ArcSwap;
+use axum::{extract::Request, http::Uri, response::IntoResponse, routing::get, Router};
+use clap::Parser;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::trace::{self, TraceLayer};
 use tracing::Level;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use utoipa::OpenApi;
 use utoipa_rapidoc::RapiDoc;
 
-use crate::config::Config;
+use crate::cli::{Cli, Command};
+use crate::config::{reload, Config};
 use crate::state::AppState;
 
+type LogHandle = tracing_subscriber::reload::Handle<EnvFilter, tracing_subscriber::Registry>;
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .compact()
+async fn main() {
+    let (filter, log) = tracing_subscriber::reload::Layer::new(EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_writer(std::io::stderr)
+                .compact(),
+        )
         .init();
 
-    let config = match Config::open(Config::data_dir()?.join("config.toml").as_path()) {
-        Ok(config) => config,
-        Err(_) => Config::new(),
+    dotenvy::dotenv().ok();
+
+    let cli = Cli::parse();
+    let source = config::Source {
+        path: cli.config,
+        data_dir: cli.data_dir,
+        overrides: cli.overrides,
     };
 
-    let pool = db::create_pool(config.database_url());
+    if let Err(e) = run(cli.command.unwrap_or(Command::Serve), source, log).await {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(command: Command, source: config::Source, log: LogHandle) -> Result<(), cli::Error> {
+    if let Command::Config(command) = command {
+        return cli::config::run(command, source).await;
+    }
 
-    db::run_migrations(&pool).await?;
+    let config = Config::load(&source)?;
+    set_filter(&log, &config);
+
+    match command {
+        Command::Serve => serve(config, source, log).await,
+        Command::Migrate(command) => cli::migrate::run(command, config).await,
+        Command::User(command) => cli::user::run(command, config).await,
+        Command::Avatars(command) => cli::avatars::run(command, config).await,
+        Command::Backup(command) => cli::backup::backup(command, config).await,
+        Command::Restore(command) => cli::backup::restore(command, config).await,
+        Command::Config(_) => Ok(()),
+    }
+}
+
+fn set_filter(log: &LogHandle, config: &Config) {
+    if let Err(e) = log.reload(EnvFilter::new(&config.log.filter)) {
+        tracing::error!("Failed to apply log filter: {}", e);
+    }
+}
+
+async fn serve(config: Config, source: config::Source, log: LogHandle) -> Result<(), cli::Error> {
+    config.check()?;
+
+    let pool = db::connect(&config).await?;
+
+    if config.database.auto_migrate {
+        db::run_migrations(&pool).await?;
+    } else {
+        let pending = db::pending_migrations(&pool).await?;
+        if !pending.is_empty() {
+            return Err(db::errors::DatabaseError::PendingMigrations(pending).into());
+        }
+    }
+
+    let storage = storage::from_config(&config)?;
+    let stored = settings::load(&pool).await?;
 
     let state = Arc::new(AppState {
         database: pool.clone(),
-        config: config.clone(),
+        config: ArcSwap::from_pointee(config.clone()),
+        storage,
+        users: Arc::new(repository::sql::Sql::new(pool.clone())),
+        settings: ArcSwap::from_pointee(stored),
+        seen: Default::default(),
+        appending: Default::default(),
     });
 
+    tokio::spawn({
+        let state = state.clone();
+        async move { reload::watch(&state.config, source, |config| set_filter(&log, config)).await }
+    });
+
+    tokio::spawn(backup::schedule::run(state.clone()));
+
     let app = Router::new()
         .nest("/resources", resources::routes(state.clone()))
         .nest("/api", api::routes(state))
@@ -47,9 +124,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/*frontend", get(frontend_handler))
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+                .make_span_with(|req: &Request| {
+                    tracing::info_span!(
+                        "request",
+                        method = %req.method(),
+                        uri = %req.uri(),
+                        version = ?req.version(),
+                        id = api::middleware::current_request_id().unwrap_or_default(),
+                    )
+                })
                 .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
-        );
+        )
+        .layer(axum::middleware::from_fn(api::middleware::request_id));
 
     let address: SocketAddr =
         format!("{}:{}", config.server.address, config.server.port).parse()?;
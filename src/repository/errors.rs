@@ -0,0 +1,27 @@
+use crate::db::errors::DatabaseError;
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// The login or email is taken.
+    Exists,
+    RegistrationClosed,
+    Database(DatabaseError),
+}
+
+impl std::error::Error for RepositoryError {}
+
+impl std::fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Exists => write!(f, "User already exists"),
+            Self::RegistrationClosed => write!(f, "Registration of new users is closed"),
+            Self::Database(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<DatabaseError> for RepositoryError {
+    fn from(e: DatabaseError) -> Self {
+        Self::Database(e)
+    }
+}
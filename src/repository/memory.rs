@@ -0,0 +1,233 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::db::user::{NewUser, User, UserChanges};
+
+use super::{errors::RepositoryError, AvatarChange, Deleted, Page, Read, UserRepository};
+
+/// Users kept in memory, for tests that do not need a database.
+///
+/// Blobs are not tracked: previous avatars are reported as not being blobs
+/// and deleting a user never leaves a blob unreferenced.
+#[derive(Default)]
+pub struct Memory {
+    users: Mutex<Vec<User>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+
+    fn users(&self) -> std::sync::MutexGuard<'_, Vec<User>> {
+        // A panicking test must not take the others down with it.
+        self.users
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn find_with(&self, predicate: impl Fn(&User) -> bool) -> Option<User> {
+        self.users().iter().find(|user| predicate(user)).cloned()
+    }
+}
+
+#[async_trait]
+impl UserRepository for Memory {
+    async fn find(&self, id: uuid::Uuid, _: Read) -> Result<Option<User>, RepositoryError> {
+        Ok(self.find_with(|user| user.id == id))
+    }
+
+    async fn find_by_login(&self, login: &str, _: Read) -> Result<Option<User>, RepositoryError> {
+        Ok(self.find_with(|user| user.login == login))
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        Ok(self.find_with(|user| user.email == email))
+    }
+
+    async fn list(&self, page: Page) -> Result<Vec<User>, RepositoryError> {
+        let mut users = self.users().clone();
+        users.sort_by(|a, b| a.login.cmp(&b.login));
+
+        Ok(users
+            .into_iter()
+            .skip(page.offset.max(0) as usize)
+            .take(page.limit.map_or(usize::MAX, |limit| limit.max(0) as usize))
+            .collect())
+    }
+
+    async fn create(&self, user: NewUser) -> Result<User, RepositoryError> {
+        let mut users = self.users();
+        if users
+            .iter()
+            .any(|other| other.login == user.login || other.email == user.email)
+        {
+            return Err(RepositoryError::Exists);
+        }
+
+        let now = chrono::Utc::now();
+        let user = User {
+            id: uuid::Uuid::new_v4(),
+            login: user.login,
+            hashed_password: user.hashed_password,
+            name: user.name,
+            email: user.email,
+            is_admin: user.is_admin,
+            avatar: user.avatar,
+            avatar_style: user.avatar_style,
+            created_at: now,
+            updated_at: now,
+            last_login_at: None,
+            last_seen_at: None,
+        };
+        users.push(user.clone());
+
+        Ok(user)
+    }
+
+    async fn register(&self, user: NewUser, open: bool) -> Result<User, RepositoryError> {
+        let first = self.users().is_empty();
+        if !first && !open {
+            return Err(RepositoryError::RegistrationClosed);
+        }
+
+        self.create(NewUser {
+            is_admin: first,
+            ..user
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        id: uuid::Uuid,
+        changes: UserChanges,
+    ) -> Result<Option<User>, RepositoryError> {
+        let mut users = self.users();
+        if let Some(email) = &changes.email {
+            if users
+                .iter()
+                .any(|other| other.id != id && &other.email == email)
+            {
+                return Err(RepositoryError::Exists);
+            }
+        }
+
+        let Some(user) = users.iter_mut().find(|user| user.id == id) else {
+            return Ok(None);
+        };
+        if let Some(hashed_password) = changes.hashed_password {
+            user.hashed_password = hashed_password;
+        }
+        if let Some(name) = changes.name {
+            user.name = name;
+        }
+        if let Some(email) = changes.email {
+            user.email = email;
+        }
+        if let Some(is_admin) = changes.is_admin {
+            user.is_admin = is_admin;
+        }
+        if let Some(avatar_style) = changes.avatar_style {
+            user.avatar_style = avatar_style;
+        }
+        user.updated_at = chrono::Utc::now();
+
+        Ok(Some(user.clone()))
+    }
+
+    async fn replace_avatar(
+        &self,
+        id: uuid::Uuid,
+        avatar: String,
+    ) -> Result<Option<AvatarChange>, RepositoryError> {
+        Ok(self
+            .users()
+            .iter_mut()
+            .find(|user| user.id == id)
+            .map(|user| {
+                user.updated_at = chrono::Utc::now();
+                AvatarChange {
+                    previous: std::mem::replace(&mut user.avatar, avatar),
+                    remaining: None,
+                }
+            }))
+    }
+
+    async fn record_login(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
+        let now = chrono::Utc::now();
+
+        Ok(self
+            .users()
+            .iter_mut()
+            .find(|user| user.id == id)
+            .map(|user| {
+                user.last_login_at = Some(now);
+                user.last_seen_at = Some(now);
+                user.clone()
+            }))
+    }
+
+    async fn record_seen(&self, id: uuid::Uuid) -> Result<(), RepositoryError> {
+        let now = chrono::Utc::now();
+
+        if let Some(user) = self.users().iter_mut().find(|user| user.id == id) {
+            user.last_seen_at = Some(now);
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: uuid::Uuid) -> Result<Option<Deleted>, RepositoryError> {
+        let mut users = self.users();
+
+        Ok(users
+            .iter()
+            .position(|user| user.id == id)
+            .map(|index| Deleted {
+                user: users.remove(index),
+                unreferenced: false,
+            }))
+    }
+}
+
+#[tokio::test]
+async fn test_register() {
+    let users = Memory::new();
+    let new_user = |login: &str| NewUser {
+        login: login.to_string(),
+        hashed_password: String::new(),
+        name: login.to_string(),
+        email: format!("{}@elnafo.ru", login),
+        is_admin: false,
+        avatar: String::new(),
+        avatar_style: String::new(),
+    };
+
+    let first = users.register(new_user("first"), false).await.unwrap();
+    assert!(first.is_admin);
+    assert!(matches!(
+        users.register(new_user("second"), false).await,
+        Err(RepositoryError::RegistrationClosed)
+    ));
+
+    let second = users.register(new_user("second"), true).await.unwrap();
+    assert!(!second.is_admin);
+    assert!(matches!(
+        users.register(new_user("second"), true).await,
+        Err(RepositoryError::Exists)
+    ));
+
+    let page = Page {
+        offset: 1,
+        limit: Some(1),
+    };
+    let listed = users.list(page).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].login, "second");
+
+    let deleted = users.delete(first.id).await.unwrap().unwrap();
+    assert_eq!(deleted.user.login, "first");
+    assert!(users.find(first.id, Read::Primary).await.unwrap().is_none());
+}
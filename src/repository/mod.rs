@@ -0,0 +1,89 @@
+//! Access to stored records, keeping the SQL out of the handlers.
+
+pub mod errors;
+pub mod memory;
+pub mod sql;
+
+use async_trait::async_trait;
+
+use crate::db::user::{NewUser, User, UserChanges};
+
+use errors::RepositoryError;
+
+/// Slice of a listing; without a limit everything from the offset on is returned.
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Page {
+    #[serde(default)]
+    pub offset: i64,
+    pub limit: Option<i64>,
+}
+
+/// Database a lookup may be answered from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Read {
+    /// The primary, for lookups that authenticate or decide on changes.
+    Primary,
+    /// A read replica if there is one, unless the given user changed something moments ago.
+    Replica(Option<uuid::Uuid>),
+}
+
+/// Previous avatar of a user whose avatar was replaced.
+#[derive(Debug)]
+pub struct AvatarChange {
+    pub previous: String,
+    /// References left to the previous avatar blob, `None` when it is not a blob.
+    pub remaining: Option<i64>,
+}
+
+/// Outcome of deleting a user.
+#[derive(Debug)]
+pub struct Deleted {
+    pub user: User,
+    /// Whether a blob of the user's files or avatar is not referenced anymore.
+    pub unreferenced: bool,
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find(&self, id: uuid::Uuid, read: Read) -> Result<Option<User>, RepositoryError>;
+
+    async fn find_by_login(&self, login: &str, read: Read)
+        -> Result<Option<User>, RepositoryError>;
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, RepositoryError>;
+
+    /// Users ordered by login.
+    async fn list(&self, page: Page) -> Result<Vec<User>, RepositoryError>;
+
+    /// Inserts the user as given, failing with `Exists` if the login or email is taken.
+    async fn create(&self, user: NewUser) -> Result<User, RepositoryError>;
+
+    /// Inserts a user signing up, making the first one the administrator.
+    ///
+    /// Unless `open`, only the first user may register.
+    async fn register(&self, user: NewUser, open: bool) -> Result<User, RepositoryError>;
+
+    /// Applies the changes, returning the updated user if it exists.
+    async fn update(
+        &self,
+        id: uuid::Uuid,
+        changes: UserChanges,
+    ) -> Result<Option<User>, RepositoryError>;
+
+    /// Sets the avatar and releases the blob of the previous one.
+    async fn replace_avatar(
+        &self,
+        id: uuid::Uuid,
+        avatar: String,
+    ) -> Result<Option<AvatarChange>, RepositoryError>;
+
+    /// Records a successful login, which also counts as being seen, returning the updated user.
+    async fn record_login(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError>;
+
+    /// Records an authenticated request of the user.
+    async fn record_seen(&self, id: uuid::Uuid) -> Result<(), RepositoryError>;
+
+    /// Deletes the user with their files, releasing the blobs they referenced.
+    async fn delete(&self, id: uuid::Uuid) -> Result<Option<Deleted>, RepositoryError>;
+}
@@ -0,0 +1,332 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+
+use crate::db::{
+    self,
+    schema::{files, users},
+    types::{Id, Timestamp},
+    user::{NewUser, User, UserChanges},
+    Connection, Isolation, MultiBackend, Pool,
+};
+
+use super::{errors::RepositoryError, AvatarChange, Deleted, Page, Read, UserRepository};
+
+/// Users stored in the `users` table, of any of the database engines.
+pub struct Sql {
+    pool: Pool,
+}
+
+impl Sql {
+    pub fn new(pool: Pool) -> Self {
+        Sql { pool }
+    }
+
+    async fn find_by<F>(&self, read: Read, filter: F) -> Result<Option<User>, RepositoryError>
+    where
+        F: FnOnce(
+                users::BoxedQuery<'static, MultiBackend>,
+            ) -> users::BoxedQuery<'static, MultiBackend>
+            + Send
+            + 'static,
+    {
+        let query = move |conn: &mut Connection| {
+            filter(users::table.into_boxed())
+                .select(User::as_select())
+                .first(conn)
+                .optional()
+        };
+
+        Ok(match read {
+            Read::Primary => db::execute(&self.pool, query).await?,
+            Read::Replica(reader) => db::execute_read(&self.pool, reader, query).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl UserRepository for Sql {
+    async fn find(&self, id: uuid::Uuid, read: Read) -> Result<Option<User>, RepositoryError> {
+        self.find_by(read, move |query| query.filter(users::id.eq(Id(id))))
+            .await
+    }
+
+    async fn find_by_login(
+        &self,
+        login: &str,
+        read: Read,
+    ) -> Result<Option<User>, RepositoryError> {
+        let login = login.to_string();
+        self.find_by(read, move |query| query.filter(users::login.eq(login)))
+            .await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        let email = email.to_string();
+        self.find_by(Read::Primary, move |query| {
+            query.filter(users::email.eq(email))
+        })
+        .await
+    }
+
+    async fn list(&self, page: Page) -> Result<Vec<User>, RepositoryError> {
+        Ok(db::execute(&self.pool, move |conn| {
+            // SQLite only accepts an offset after a limit.
+            users::table
+                .order(users::login)
+                .offset(page.offset)
+                .limit(page.limit.unwrap_or(i64::MAX))
+                .select(User::as_select())
+                .load(conn)
+        })
+        .await?)
+    }
+
+    async fn create(&self, user: NewUser) -> Result<User, RepositoryError> {
+        db::execute(&self.pool, move |conn| insert(conn, &user)).await?
+    }
+
+    async fn register(&self, user: NewUser, open: bool) -> Result<User, RepositoryError> {
+        // Serializable, so that concurrent registrations cannot both become the first administrator.
+        db::transaction(&self.pool, Isolation::Serializable, move |conn| {
+            let count = users::table.count().get_result::<i64>(conn)?;
+            if count > 0 && !open {
+                return Ok(Err(RepositoryError::RegistrationClosed));
+            }
+
+            let exists = users::table
+                .filter(users::login.eq(&user.login))
+                .or_filter(users::email.eq(&user.email))
+                .select(users::id)
+                .first::<uuid::Uuid>(conn)
+                .optional()?;
+            if exists.is_some() {
+                return Ok(Err(RepositoryError::Exists));
+            }
+
+            insert(
+                conn,
+                &NewUser {
+                    is_admin: count == 0,
+                    ..user.clone()
+                },
+            )
+        })
+        .await?
+    }
+
+    async fn update(
+        &self,
+        id: uuid::Uuid,
+        changes: UserChanges,
+    ) -> Result<Option<User>, RepositoryError> {
+        if changes.is_empty() {
+            return self.find(id, Read::Primary).await;
+        }
+
+        match db::execute(&self.pool, move |conn| {
+            diesel::update(users::table.find(Id(id)))
+                .set(changes)
+                .returning(users::all_columns)
+                .get_result(conn)
+                .optional()
+        })
+        .await
+        {
+            Err(db::errors::DatabaseError::Query(Error::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                _,
+            ))) => Err(RepositoryError::Exists),
+            result => Ok(result?),
+        }
+    }
+
+    async fn replace_avatar(
+        &self,
+        id: uuid::Uuid,
+        avatar: String,
+    ) -> Result<Option<AvatarChange>, RepositoryError> {
+        Ok(
+            db::transaction(&self.pool, Isolation::ReadCommitted, move |conn| {
+                let avatar_of = users::table.find(Id(id)).select(users::avatar);
+                let previous = match conn {
+                    #[cfg(feature = "postgres")]
+                    Connection::Postgres(conn) => avatar_of.for_update().first::<String>(conn),
+                    #[cfg(feature = "sqlite")]
+                    Connection::Sqlite(conn) => avatar_of.first::<String>(conn),
+                }
+                .optional()?;
+                let Some(previous) = previous else {
+                    return Ok(None);
+                };
+
+                diesel::update(users::table.find(Id(id)))
+                    .set(users::avatar.eq(&avatar))
+                    .execute(conn)?;
+                let remaining = db::blob::release(conn, &previous)?;
+
+                Ok(Some(AvatarChange {
+                    previous,
+                    remaining,
+                }))
+            })
+            .await?,
+        )
+    }
+
+    async fn record_login(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
+        let now = Timestamp::now();
+
+        Ok(db::execute(&self.pool, move |conn| {
+            diesel::update(users::table.find(Id(id)))
+                .set((users::last_login_at.eq(now), users::last_seen_at.eq(now)))
+                .returning(users::all_columns)
+                .get_result(conn)
+                .optional()
+        })
+        .await?)
+    }
+
+    async fn record_seen(&self, id: uuid::Uuid) -> Result<(), RepositoryError> {
+        let now = Timestamp::now();
+
+        db::execute(&self.pool, move |conn| {
+            diesel::update(users::table.find(Id(id)))
+                .set(users::last_seen_at.eq(now))
+                .execute(conn)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: uuid::Uuid) -> Result<Option<Deleted>, RepositoryError> {
+        Ok(
+            db::transaction(&self.pool, Isolation::ReadCommitted, move |conn| {
+                // Files are removed by the cascade, their blobs have to be released explicitly.
+                let blobs = files::table
+                    .filter(files::owner_id.eq(Id(id)))
+                    .filter(files::hash.is_not_null())
+                    .select(files::hash.assume_not_null())
+                    .get_results::<String>(conn)?;
+
+                let user = diesel::delete(users::table.find(Id(id)))
+                    .returning(users::all_columns)
+                    .get_result::<User>(conn)
+                    .optional()?;
+                let Some(user) = user else {
+                    return Ok(None);
+                };
+
+                let mut unreferenced = false;
+                for blob in blobs.iter().chain(Some(&user.avatar)) {
+                    unreferenced |= db::blob::release(conn, blob)? == Some(0);
+                }
+
+                Ok(Some(Deleted { user, unreferenced }))
+            })
+            .await?,
+        )
+    }
+}
+
+/// Inserts the user, reporting a taken login or email as `Exists`.
+///
+/// Columns are listed one by one: the dialect shared by all engines cannot insert
+/// an `Insertable` struct.
+fn insert(conn: &mut Connection, user: &NewUser) -> QueryResult<Result<User, RepositoryError>> {
+    match diesel::insert_into(users::table)
+        .values((
+            users::login.eq(&user.login),
+            users::hashed_password.eq(&user.hashed_password),
+            users::name.eq(&user.name),
+            users::email.eq(&user.email),
+            users::is_admin.eq(user.is_admin),
+            users::avatar.eq(&user.avatar),
+            users::avatar_style.eq(&user.avatar_style),
+        ))
+        .returning(users::all_columns)
+        .get_result(conn)
+    {
+        Ok(user) => Ok(Ok(user)),
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Ok(Err(RepositoryError::Exists))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_register_sqlite() {
+    let dir = std::env::temp_dir().join(format!("elnafo-test-sqlite-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut config = crate::config::Config::default();
+    config.database.url = Some(format!("sqlite://{}", dir.join("elnafo.db").display()));
+
+    let pool = db::create_pool(&config).unwrap();
+    db::run_migrations(&pool).await.unwrap();
+
+    let users = Sql::new(pool);
+    let new_user = |login: &str| NewUser {
+        login: login.to_string(),
+        hashed_password: String::new(),
+        name: login.to_string(),
+        email: format!("{}@elnafo.ru", login),
+        is_admin: false,
+        avatar: String::new(),
+        avatar_style: String::new(),
+    };
+
+    let first = users.register(new_user("first"), false).await.unwrap();
+    assert!(first.is_admin);
+    assert!(matches!(
+        users.register(new_user("second"), false).await,
+        Err(RepositoryError::RegistrationClosed)
+    ));
+
+    let second = users.register(new_user("second"), true).await.unwrap();
+    assert!(!second.is_admin);
+    assert!(matches!(
+        users.register(new_user("second"), true).await,
+        Err(RepositoryError::Exists)
+    ));
+
+    let page = Page {
+        offset: 1,
+        limit: Some(1),
+    };
+    let listed = users.list(page).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, second.id);
+    let page = Page {
+        offset: 1,
+        limit: None,
+    };
+    assert_eq!(users.list(page).await.unwrap().len(), 1);
+
+    let logged_in = users.record_login(second.id).await.unwrap().unwrap();
+    assert!(logged_in.last_login_at.is_some());
+    assert_eq!(logged_in.last_login_at, logged_in.last_seen_at);
+    assert_eq!(logged_in.updated_at, second.updated_at);
+
+    users.record_seen(second.id).await.unwrap();
+    let seen = users.find(second.id, Read::Primary).await.unwrap().unwrap();
+    assert_eq!(seen.updated_at, second.updated_at);
+
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let changes = UserChanges {
+        name: Some("Second".to_string()),
+        ..UserChanges::default()
+    };
+    users.update(second.id, changes).await.unwrap().unwrap();
+    // SQLite's RETURNING does not see what the AFTER UPDATE trigger writes.
+    let renamed = users.find(second.id, Read::Primary).await.unwrap().unwrap();
+    assert!(renamed.updated_at > seen.updated_at);
+
+    let deleted = users.delete(first.id).await.unwrap().unwrap();
+    assert_eq!(deleted.user.login, "first");
+    assert!(users.find(first.id, Read::Primary).await.unwrap().is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
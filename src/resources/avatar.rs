@@ -0,0 +1,261 @@
+use image::{codecs::avif::AvifEncoder, imageops::FilterType, DynamicImage, ImageFormat};
+
+use crate::api::errors::UploadError;
+use crate::storage::{blobs, errors::StorageError, Storage};
+
+/// Square sizes (in pixels) pre-rendered for every uploaded avatar.
+pub const SIZES: [u32; 5] = [32, 64, 128, 256, 512];
+
+/// Size served when the client does not ask for a specific one.
+pub const DEFAULT_SIZE: u32 = 256;
+
+/// Formats encoded in addition to the base PNG/JPEG variant, best first.
+pub const MODERN_FORMATS: [ImageFormat; 2] = [ImageFormat::Avif, ImageFormat::WebP];
+
+pub struct Variant {
+    pub size: u32,
+    pub format: ImageFormat,
+    pub content: Vec<u8>,
+}
+
+pub fn file_name(size: u32, format: ImageFormat) -> String {
+    format!("{}.{}", size, format.extensions_str()[0])
+}
+
+/// Smallest pre-rendered size that is not less than the requested one.
+pub fn fit_size(requested: Option<u32>) -> u32 {
+    let requested = requested.unwrap_or(DEFAULT_SIZE);
+
+    SIZES
+        .into_iter()
+        .find(|&size| size >= requested)
+        .unwrap_or(SIZES[SIZES.len() - 1])
+}
+
+/// Formats accepted for upload, detected by magic bytes rather than the client's MIME type.
+pub const ALLOWED_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+];
+
+/// Decodes the uploaded image, center-crops it to a square and renders every size in `SIZES`.
+///
+/// Images are re-encoded from raw pixels, so any metadata (EXIF, ICC, comments) is dropped.
+/// JPEG uploads are kept as JPEG, everything else is stored as PNG, and each size is also
+/// encoded to every format in `MODERN_FORMATS`.
+///
+/// Dimensions are checked from the image header before decoding, so a small file claiming
+/// huge dimensions is rejected without allocating the pixel buffer.
+pub fn render(data: &[u8], max_dimension: u32) -> Result<Vec<Variant>, UploadError> {
+    let format = image::guess_format(data)
+        .ok()
+        .filter(|format| ALLOWED_FORMATS.contains(format))
+        .ok_or(UploadError::UnsupportedFormat)?;
+
+    let reader = || image::io::Reader::with_format(std::io::Cursor::new(data), format);
+    let (width, height) = reader()
+        .into_dimensions()
+        .map_err(|_| UploadError::Corrupted)?;
+    if width == 0 || height == 0 {
+        return Err(UploadError::Corrupted);
+    }
+    if width > max_dimension || height > max_dimension {
+        return Err(UploadError::Dimensions);
+    }
+
+    let mut limits = image::io::Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+
+    let mut reader = reader();
+    reader.limits(limits);
+    let square = crop_square(reader.decode().map_err(|e| match e {
+        image::ImageError::Limits(_) => UploadError::Dimensions,
+        _ => UploadError::Corrupted,
+    })?);
+
+    let base_format = match format {
+        ImageFormat::Jpeg => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    };
+
+    let mut variants = Vec::new();
+    for size in SIZES {
+        let resized = square.resize_exact(size, size, FilterType::Lanczos3);
+
+        for format in std::iter::once(base_format).chain(MODERN_FORMATS) {
+            variants.push(Variant {
+                size,
+                format,
+                content: encode(&resized, format).map_err(|_| UploadError::Encode)?,
+            });
+        }
+    }
+
+    Ok(variants)
+}
+
+fn encode(img: &DynamicImage, format: ImageFormat) -> image::ImageResult<Vec<u8>> {
+    let mut content = Vec::new();
+
+    match format {
+        // JPEG has no alpha channel.
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_to(&mut std::io::Cursor::new(&mut content), format)?,
+        // Default AVIF speed is far too slow to run on every upload, use the fastest one.
+        ImageFormat::Avif => DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_with_encoder(AvifEncoder::new_with_speed_quality(&mut content, 10, 75))?,
+        _ => DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_to(&mut std::io::Cursor::new(&mut content), format)?,
+    }
+
+    Ok(content)
+}
+
+/// Storage key of an avatar variant.
+pub fn key(avatar_id: &str, size: u32, format: ImageFormat) -> String {
+    format!("{}/{}", prefix(avatar_id), file_name(size, format))
+}
+
+/// Storage key prefix holding every variant of an avatar.
+///
+/// Avatars are content-addressed blobs; ids that are not hashes come from before
+/// the blob store and live in the flat `avatars/` directory.
+pub fn prefix(avatar_id: &str) -> String {
+    if blobs::is_hash(avatar_id) {
+        blobs::prefix(avatar_id)
+    } else {
+        format!("avatars/{}", avatar_id)
+    }
+}
+
+/// Whether every variant of the avatar has been stored.
+pub async fn is_stored(storage: &dyn Storage, avatar_id: &str) -> Result<bool, StorageError> {
+    // `store` writes the variants in `render` order, so the last one marks completion.
+    storage
+        .exists(&key(
+            avatar_id,
+            SIZES[SIZES.len() - 1],
+            MODERN_FORMATS[MODERN_FORMATS.len() - 1],
+        ))
+        .await
+}
+
+/// Writes rendered variants of the avatar into the storage.
+pub async fn store(
+    storage: &dyn Storage,
+    avatar_id: &str,
+    variants: Vec<Variant>,
+) -> Result<(), StorageError> {
+    for variant in variants {
+        storage
+            .put(
+                &key(avatar_id, variant.size, variant.format),
+                variant.content,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Finds the stored variant of the given size in the first available format of `preferred`,
+/// falling back to the base PNG/JPEG variant.
+pub async fn find(
+    storage: &dyn Storage,
+    avatar_id: &str,
+    size: u32,
+    preferred: &[ImageFormat],
+) -> Result<Option<(String, ImageFormat)>, StorageError> {
+    for format in preferred
+        .iter()
+        .copied()
+        .chain([ImageFormat::Png, ImageFormat::Jpeg])
+    {
+        let key = key(avatar_id, size, format);
+
+        if storage.exists(&key).await? {
+            return Ok(Some((key, format)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Picks the modern formats acceptable for the client from its `Accept` header, best first.
+///
+/// Formats listed with `q=0` are treated as refused; wildcards are ignored because browsers
+/// send `image/*` even when they cannot decode AVIF.
+pub fn negotiate(accept: Option<&str>) -> Vec<ImageFormat> {
+    let accepted = accept
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let mime = parts.next()?;
+            let refused = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q <= 0.0)
+            });
+
+            (!refused).then_some(mime)
+        })
+        .collect::<Vec<&str>>();
+
+    MODERN_FORMATS
+        .into_iter()
+        .filter(|format| accepted.contains(&format.to_mime_type()))
+        .collect()
+}
+
+fn crop_square(img: DynamicImage) -> DynamicImage {
+    let side = img.width().min(img.height());
+    let x = (img.width() - side) / 2;
+    let y = (img.height() - side) / 2;
+
+    img.crop_imm(x, y, side, side)
+}
+
+#[test]
+fn test_fit_size() {
+    assert_eq!(fit_size(None), DEFAULT_SIZE);
+    assert_eq!(fit_size(Some(1)), 32);
+    assert_eq!(fit_size(Some(100)), 128);
+    assert_eq!(fit_size(Some(4096)), 512);
+}
+
+#[test]
+fn test_negotiate() {
+    assert_eq!(negotiate(None), vec![]);
+    assert_eq!(
+        negotiate(Some("image/avif,image/webp,image/apng,image/*,*/*;q=0.8")),
+        vec![ImageFormat::Avif, ImageFormat::WebP]
+    );
+    assert_eq!(
+        negotiate(Some("image/webp, image/avif;q=0")),
+        vec![ImageFormat::WebP]
+    );
+    assert_eq!(negotiate(Some("image/*")), vec![]);
+}
+
+#[test]
+fn test_render_rejects() {
+    let mut png = Vec::new();
+    DynamicImage::new_rgb8(64, 32)
+        .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+
+    assert!(matches!(
+        render(b"GIF89a", 4096),
+        Err(UploadError::Corrupted)
+    ));
+    assert!(matches!(
+        render(b"%PDF-1.7", 4096),
+        Err(UploadError::UnsupportedFormat)
+    ));
+    assert!(matches!(render(&png, 48), Err(UploadError::Dimensions)));
+}
@@ -0,0 +1,323 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
+
+use crate::storage::{errors::StorageError, Storage};
+
+/// For content whose URL changes whenever the content does.
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// For content that may change under the same URL, always revalidated with the ETag.
+pub const REVALIDATE: &str = "public, no-cache";
+
+/// For content only its owner may see, never stored by shared caches.
+pub const PRIVATE: &str = "private, no-cache";
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+enum Content {
+    Memory(Vec<u8>),
+    /// An object read from the storage only as far as the request needs.
+    Stored {
+        storage: Arc<dyn Storage>,
+        key: String,
+        size: u64,
+    },
+}
+
+/// A resource body with everything needed to answer conditional and range requests.
+pub struct Cached {
+    content: Content,
+    pub mime: String,
+    pub etag: String,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub cache_control: &'static str,
+}
+
+impl Cached {
+    /// Creates a resource with a strong ETag derived from the SHA-256 of its content.
+    pub fn new(content: Vec<u8>, mime: impl Into<String>, cache_control: &'static str) -> Self {
+        let hash = Sha256::digest(&content);
+
+        Cached {
+            content: Content::Memory(content),
+            mime: mime.into(),
+            etag: etag(&hash),
+            last_modified: None,
+            cache_control,
+        }
+    }
+
+    /// Creates a resource streamed from the storage, tagged with the hex encoded
+    /// SHA-256 of its content.
+    pub fn stored(
+        storage: Arc<dyn Storage>,
+        key: String,
+        size: u64,
+        hash: &str,
+        mime: impl Into<String>,
+        cache_control: &'static str,
+    ) -> Self {
+        Cached {
+            content: Content::Stored { storage, key, size },
+            mime: mime.into(),
+            etag: quoted(hash),
+            last_modified: None,
+            cache_control,
+        }
+    }
+
+    pub fn with_etag(mut self, hash: &[u8]) -> Self {
+        self.etag = etag(hash);
+        self
+    }
+
+    pub fn with_last_modified(mut self, last_modified: Option<DateTime<Utc>>) -> Self {
+        // HTTP dates have a one second resolution.
+        self.last_modified =
+            last_modified.and_then(|date| DateTime::from_timestamp(date.timestamp(), 0));
+        self
+    }
+
+    /// Builds a `200`, `206`, `304` or `416` response depending on the request headers.
+    pub async fn respond(self, request: &HeaderMap) -> Result<Response, StorageError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, value(&self.etag));
+        headers.insert(header::CACHE_CONTROL, value(self.cache_control));
+        headers.insert(header::ACCEPT_RANGES, value("bytes"));
+        if let Some(last_modified) = self.last_modified {
+            headers.insert(
+                header::LAST_MODIFIED,
+                value(&last_modified.format(HTTP_DATE).to_string()),
+            );
+        }
+
+        if self.not_modified(request) {
+            return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+        }
+
+        headers.insert(header::CONTENT_TYPE, value(&self.mime));
+
+        let length = match &self.content {
+            Content::Memory(content) => content.len() as u64,
+            Content::Stored { size, .. } => *size,
+        };
+        let range = request
+            .get(header::RANGE)
+            .and_then(|range| range.to_str().ok())
+            .filter(|_| self.if_range(request));
+
+        let (status, range) = match range.map(|range| parse_range(range, length)) {
+            Some(Ok(Some((start, end)))) => {
+                headers.insert(
+                    header::CONTENT_RANGE,
+                    value(&format!("bytes {}-{}/{}", start, end, length)),
+                );
+
+                (StatusCode::PARTIAL_CONTENT, start..end + 1)
+            }
+            Some(Err(_)) => {
+                headers.insert(header::CONTENT_RANGE, value(&format!("bytes */{}", length)));
+
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+            }
+            _ => (StatusCode::OK, 0..length),
+        };
+
+        let body = match self.content {
+            Content::Memory(content) if range.end - range.start == length => Body::from(content),
+            Content::Memory(content) => {
+                Body::from(content[range.start as usize..range.end as usize].to_vec())
+            }
+            Content::Stored { .. } if range.is_empty() => Body::empty(),
+            Content::Stored { storage, key, .. } => {
+                headers.insert(
+                    header::CONTENT_LENGTH,
+                    HeaderValue::from(range.end - range.start),
+                );
+
+                // Backend errors are not `Sync`, which bodies require; the response is
+                // already underway by then, so only the message is kept.
+                let stream = storage.get_range(&key, range).await?.map_err(move |e| {
+                    tracing::warn!("Failed to stream {}: {}", key, e);
+                    std::io::Error::other(e.to_string())
+                });
+
+                Body::from_stream(stream)
+            }
+        };
+
+        Ok((status, headers, body).into_response())
+    }
+
+    fn not_modified(&self, request: &HeaderMap) -> bool {
+        // If-Modified-Since is ignored when If-None-Match is present (RFC 9110, 13.1.3).
+        if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+            return if_none_match.to_str().is_ok_and(|tags| {
+                tags.split(',')
+                    .map(|tag| tag.trim().trim_start_matches("W/"))
+                    .any(|tag| tag == "*" || tag == self.etag)
+            });
+        }
+
+        match (self.last_modified, date(request, header::IF_MODIFIED_SINCE)) {
+            (Some(last_modified), Some(since)) => last_modified <= since,
+            _ => false,
+        }
+    }
+
+    fn if_range(&self, request: &HeaderMap) -> bool {
+        let Some(if_range) = request.get(header::IF_RANGE) else {
+            return true;
+        };
+
+        // Only an exact date identifies the representation the range belongs to (RFC 9110, 13.1.5).
+        match (self.last_modified, date(request, header::IF_RANGE)) {
+            (Some(last_modified), Some(date)) => last_modified == date,
+            _ => if_range.to_str().is_ok_and(|tag| tag == self.etag),
+        }
+    }
+}
+
+/// Marks the ETag of a compressed response as weak, since the encoded bytes differ from
+/// those the strong tag stands for and must not validate ranges of either.
+pub async fn weaken_encoded(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        if let Some(etag) = headers
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"))
+        {
+            let weak = value(&format!("W/{}", etag));
+            headers.insert(header::ETAG, weak);
+        }
+    }
+
+    response
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Unsatisfiable;
+
+/// Parses a `Range` header into inclusive byte bounds.
+///
+/// Only a single `bytes` range is supported; anything else yields `Ok(None)` so the whole
+/// content is served as allowed by RFC 9110.
+pub fn parse_range(range: &str, length: u64) -> Result<Option<(u64, u64)>, Unsatisfiable> {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(length.saturating_sub(1))),
+        (Ok(start), Err(_)) if end.is_empty() => (start, length.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => {
+            (length.saturating_sub(suffix), length.saturating_sub(1))
+        }
+        _ => return Ok(None),
+    };
+
+    if length == 0 || start >= length {
+        return Err(Unsatisfiable);
+    }
+
+    Ok(Some((start, end)))
+}
+
+/// Formats a content hash as a quoted strong entity tag.
+fn etag(hash: &[u8]) -> String {
+    let hex = hash
+        .iter()
+        .take(16)
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    quoted(&hex)
+}
+
+/// Same as [`etag`] for a hex encoded hash.
+fn quoted(hex: &str) -> String {
+    format!("\"{}\"", hex.get(..32).unwrap_or(hex))
+}
+
+fn date(request: &HeaderMap, name: header::HeaderName) -> Option<DateTime<Utc>> {
+    request
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(|date| date.with_timezone(&Utc))
+}
+
+fn value(s: &str) -> HeaderValue {
+    HeaderValue::from_str(s).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+    assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 999))));
+    assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+    assert_eq!(parse_range("bytes=500-5000", 1000), Ok(Some((500, 999))));
+    assert_eq!(parse_range("bytes=1000-", 1000), Err(Unsatisfiable));
+    assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+    assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+}
+
+#[tokio::test]
+async fn test_if_range() {
+    let last_modified = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let respond = |if_range: DateTime<Utc>| async move {
+        let mut request = HeaderMap::new();
+        request.insert(header::RANGE, value("bytes=0-1"));
+        request.insert(
+            header::IF_RANGE,
+            value(&if_range.format(HTTP_DATE).to_string()),
+        );
+
+        Cached::new(vec![1, 2, 3], "application/octet-stream", REVALIDATE)
+            .with_last_modified(Some(last_modified))
+            .respond(&request)
+            .await
+            .unwrap()
+            .status()
+    };
+
+    assert_eq!(respond(last_modified).await, StatusCode::PARTIAL_CONTENT);
+    // Only an exact date validates the range, not merely a later one.
+    for date in [1_600_000_000, 1_800_000_000] {
+        let date = DateTime::from_timestamp(date, 0).unwrap();
+        assert_eq!(respond(date).await, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_weaken_encoded() {
+    let etag = |encoding: Option<&'static str>| async move {
+        let mut response = (StatusCode::OK, [(header::ETAG, "\"abc\"")]).into_response();
+        if let Some(encoding) = encoding {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_ENCODING, value(encoding));
+        }
+
+        weaken_encoded(response).await.headers()[header::ETAG].to_owned()
+    };
+
+    assert_eq!(etag(None).await, "\"abc\"");
+    assert_eq!(etag(Some("gzip")).await, "W/\"abc\"");
+}
@@ -0,0 +1,203 @@
+use image::{ImageFormat, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+
+/// Style of the avatar generated for users without an uploaded one.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    Identicon,
+    Initials,
+}
+
+impl Style {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Identicon => "identicon",
+            Self::Initials => "initials",
+        }
+    }
+}
+
+impl std::str::FromStr for Style {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "identicon" => Ok(Self::Identicon),
+            "initials" => Ok(Self::Initials),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Renders a PNG avatar of `size` pixels, deterministic for the given seed and name.
+pub fn render(seed: &[u8], name: &str, style: Style, size: u32) -> image::ImageResult<Vec<u8>> {
+    let hash = Sha256::digest(seed);
+    let img = match style {
+        Style::Identicon => identicon(&hash, size),
+        Style::Initials => initials(&hash, name, size),
+    };
+
+    let mut content = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut content), ImageFormat::Png)?;
+
+    Ok(content)
+}
+
+const BACKGROUND: Rgb<u8> = Rgb([240, 240, 240]);
+const FOREGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Symmetric 5x5 pattern in a color picked from the hash, like GitHub identicons.
+fn identicon(hash: &[u8], size: u32) -> RgbImage {
+    let color = color(hash);
+    let cell = (size / 6).max(1);
+    let offset = size.saturating_sub(cell * 5) / 2;
+
+    let mut img = RgbImage::from_pixel(size, size, BACKGROUND);
+    for row in 0..5u32 {
+        for col in 0..3u32 {
+            if hash[(row * 3 + col) as usize] & 1 == 0 {
+                continue;
+            }
+
+            for mirrored in [col, 4 - col] {
+                fill(
+                    &mut img,
+                    offset + mirrored * cell,
+                    offset + row * cell,
+                    cell,
+                    cell,
+                    color,
+                );
+            }
+        }
+    }
+
+    img
+}
+
+/// Up to two initials drawn with a 5x7 bitmap font on a color picked from the hash.
+fn initials(hash: &[u8], name: &str, size: u32) -> RgbImage {
+    let letters = name
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '-' || c == '.')
+        .filter_map(|word| word.chars().next())
+        .map(|c| c.to_ascii_uppercase())
+        .map(|c| if glyph(c).is_some() { c } else { '?' })
+        .take(2)
+        .collect::<Vec<char>>();
+    let letters = if letters.is_empty() {
+        vec!['?']
+    } else {
+        letters
+    };
+
+    let columns = letters.len() as u32 * 6 - 1;
+    let cell = (size * 5 / 8 / columns).max(1);
+    let x0 = size.saturating_sub(columns * cell) / 2;
+    let y0 = size.saturating_sub(7 * cell) / 2;
+
+    let mut img = RgbImage::from_pixel(size, size, color(hash));
+    for (n, letter) in letters.into_iter().enumerate() {
+        let rows = glyph(letter).unwrap_or_default();
+
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..5u32 {
+                if row & (0b10000 >> x) != 0 {
+                    fill(
+                        &mut img,
+                        x0 + (n as u32 * 6 + x) * cell,
+                        y0 + y as u32 * cell,
+                        cell,
+                        cell,
+                        FOREGROUND,
+                    );
+                }
+            }
+        }
+    }
+
+    img
+}
+
+fn fill(img: &mut RgbImage, x: u32, y: u32, width: u32, height: u32, color: Rgb<u8>) {
+    for py in y..(y + height).min(img.height()) {
+        for px in x..(x + width).min(img.width()) {
+            img.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// Saturated, mid-lightness color with the hue taken from the hash.
+fn color(hash: &[u8]) -> Rgb<u8> {
+    let hue = u16::from_be_bytes([hash[30], hash[31]]) as f32 / u16::MAX as f32 * 360.0;
+    let (saturation, value) = (0.55, 0.75);
+
+    let c = value * saturation;
+    let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = value - c;
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    Rgb([r, g, b].map(|channel| ((channel + m) * 255.0).round() as u8))
+}
+
+fn glyph(c: char) -> Option<[u8; 7]> {
+    Some(match c {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '?' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+        _ => return None,
+    })
+}
+
+#[test]
+fn test_render_deterministic() {
+    for style in [Style::Identicon, Style::Initials] {
+        let a = render(b"user", "John Doe", style, 64).unwrap();
+        let b = render(b"user", "John Doe", style, 64).unwrap();
+        let c = render(b"other", "John Doe", style, 64).unwrap();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+}
@@ -1,73 +1,321 @@
-use std::sync::Arc;
+pub mod avatar;
+pub mod cache;
+pub mod generated;
+
+use std::{sync::Arc, time::Duration};
 
 use axum::{
+    extract::State,
     http::{
-        header::{self, ACCEPT_ENCODING, CONTENT_TYPE, ORIGIN},
-        Method, StatusCode, Uri,
+        header::{
+            self, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_TYPE, COOKIE, IF_MODIFIED_SINCE,
+            IF_NONE_MATCH, IF_RANGE, ORIGIN, RANGE,
+        },
+        HeaderMap, Method, StatusCode, Uri,
     },
-    response::{IntoResponse, Response},
+    response::{IntoResponse, Redirect, Response},
     routing::get,
-    Router,
+    Extension, Router,
 };
 use tower_http::{
-    compression::{CompressionLayer, DefaultPredicate},
+    compression::{
+        predicate::{DefaultPredicate, Predicate},
+        CompressionLayer,
+    },
     cors::CorsLayer,
 };
 
-use crate::{config::Config, state::AppState};
+use image::ImageFormat;
+
+use crate::{
+    api::{
+        errors::Problem,
+        extract::{Path, Query},
+        middleware,
+    },
+    db::{
+        self,
+        errors::DatabaseError,
+        file::{File, Visibility},
+        schema::files,
+        types::Id,
+        user::User,
+    },
+    repository::{errors::RepositoryError, Read},
+    state::AppState,
+    storage::{blobs, errors::StorageError, Storage},
+};
+
+use self::cache::Cached;
 
 pub fn routes(state: Arc<AppState>) -> Router {
     let cors = CorsLayer::new()
         .allow_methods([Method::GET])
-        .allow_headers(vec![ORIGIN, CONTENT_TYPE, ACCEPT_ENCODING])
-        .allow_origin([
-            "http://localhost:54600".parse().unwrap(),
-            "http://localhost:5173".parse().unwrap(),
+        .allow_headers(vec![
+            ORIGIN,
+            CONTENT_TYPE,
+            ACCEPT_ENCODING,
+            IF_NONE_MATCH,
+            IF_MODIFIED_SINCE,
+            IF_RANGE,
+            RANGE,
+            AUTHORIZATION,
+            COOKIE,
         ])
+        .allow_origin(middleware::allow_origin(state.to_owned()))
         .allow_credentials(true);
 
-    let compression = CompressionLayer::new().gzip(true);
+    // A range of the compressed body would not be a range of the content.
+    let compression = CompressionLayer::new().gzip(true).compress_when(
+        DefaultPredicate::new()
+            .and(|status: StatusCode, _, _: &HeaderMap, _: &_| status == StatusCode::OK),
+    );
+    let weak_etag = axum::middleware::map_response(cache::weaken_encoded);
+
+    let jwt = axum::middleware::from_fn_with_state(state.to_owned(), middleware::jwt_auth);
 
     Router::new()
         .route("/assets/*file", get(assets))
-        .route("/avatars/*avatar_id", get(avatars).layer(compression))
+        .route(
+            "/avatars/:avatar_id",
+            get(avatars)
+                .route_layer(jwt.to_owned())
+                .layer(compression.to_owned())
+                .layer(weak_etag.to_owned()),
+        )
+        .route(
+            "/files/:file_id",
+            get(files)
+                .route_layer(jwt)
+                .layer(compression)
+                .layer(weak_etag),
+        )
         .layer(cors)
+        .with_state(state)
 }
 
-async fn assets(uri: Uri) -> Result<impl IntoResponse, ResourceError> {
+async fn assets(uri: Uri, headers: HeaderMap) -> Result<impl IntoResponse, ResourceError> {
     let path = uri.path().trim_start_matches("/assets/").to_string();
 
     match elnafo_frontend::Assets::get(&path) {
         Some(content) => {
-            let mime = mime_guess::from_path(path).first_or_octet_stream();
-            Ok(([(header::CONTENT_TYPE, mime.as_ref())], content.data).into_response())
+            let mime = mime_guess::from_path(&path).first_or_octet_stream();
+            let cache_control = if elnafo_frontend::manifest().is_fingerprinted(&path) {
+                cache::IMMUTABLE
+            } else {
+                cache::REVALIDATE
+            };
+            let last_modified = content
+                .metadata
+                .last_modified()
+                .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0));
+
+            Ok(
+                Cached::new(content.data.into_owned(), mime.as_ref(), cache_control)
+                    .with_etag(&content.metadata.sha256_hash())
+                    .with_last_modified(last_modified)
+                    .respond(&headers)
+                    .await?,
+            )
         }
         None => Err(ResourceError::NotFound),
     }
 }
 
-async fn avatars(uri: Uri) -> Result<impl IntoResponse, ResourceError> {
-    let avatar_id = uri.path().trim_start_matches("/avatars/").to_string();
-    let path = Config::data_dir().unwrap().join("avatars").join(avatar_id);
-
-    let reader = image::io::Reader::open(path.clone())
-        .map_err(|_| ResourceError::NotFound)?
-        .with_guessed_format()
-        .map_err(|_| ResourceError::BadFormat)?;
-    let format = reader.format();
-
-    let mime = format.map_or("application/octet-stream", |f| f.to_mime_type());
-    let content = reader.decode().map_err(|_| ResourceError::BadContent)?;
-
-    let mut bytes: Vec<u8> = Vec::new();
-    let _ = match format {
-        Some(format) => content
-            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
-            .map_err(|_| ResourceError::BadContent),
-        None => return Err(ResourceError::BadFormat),
+#[derive(serde::Deserialize)]
+struct AvatarQuery {
+    size: Option<u32>,
+}
+
+async fn avatars(
+    State(state): State<Arc<AppState>>,
+    Extension(reader): Extension<Option<uuid::Uuid>>,
+    Path(avatar_id): Path<String>,
+    Query(query): Query<AvatarQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ResourceError> {
+    if avatar_id.is_empty() || avatar_id.contains(['/', '\\', '.']) {
+        return Err(ResourceError::NotFound);
+    }
+
+    let size = avatar::fit_size(query.size);
+
+    // Users are referenced by their id, which keeps its URL across uploads and so has to be
+    // revalidated; the id of an uploaded avatar is the hash of its content.
+    let (avatar_id, cache_control) = match uuid::Uuid::parse_str(&avatar_id) {
+        Ok(user_id) => {
+            let user = state
+                .users
+                .find(user_id, Read::Replica(reader))
+                .await?
+                .ok_or(ResourceError::NotFound)?;
+
+            if user.avatar.is_empty() {
+                let mut response = default_avatar(&user, size, &headers).await?;
+                response
+                    .headers_mut()
+                    .insert(header::VARY, header::HeaderValue::from_static("accept"));
+
+                return Ok(response);
+            }
+
+            (user.avatar, cache::REVALIDATE)
+        }
+        Err(_) if blobs::is_hash(&avatar_id) => (avatar_id, cache::IMMUTABLE),
+        Err(_) => (avatar_id, cache::REVALIDATE),
+    };
+
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let (key, format) = avatar::find(
+        state.storage.as_ref(),
+        &avatar_id,
+        size,
+        &avatar::negotiate(accept),
+    )
+    .await?
+    .ok_or(ResourceError::NotFound)?;
+
+    let mut response = if state.config.load().storage.presigned_redirects {
+        let expires_in = Duration::from_secs(state.config.load().storage.presigned_expires_in);
+
+        match state.storage.presign(&key, expires_in).await? {
+            Some(url) => Redirect::temporary(&url).into_response(),
+            None => {
+                serve(
+                    state.storage.as_ref(),
+                    &key,
+                    format,
+                    cache_control,
+                    &headers,
+                )
+                .await?
+            }
+        }
+    } else {
+        serve(
+            state.storage.as_ref(),
+            &key,
+            format,
+            cache_control,
+            &headers,
+        )
+        .await?
+    };
+
+    response
+        .headers_mut()
+        .insert(header::VARY, header::HeaderValue::from_static("accept"));
+
+    Ok(response)
+}
+
+async fn default_avatar(
+    user: &User,
+    size: u32,
+    headers: &HeaderMap,
+) -> Result<Response, ResourceError> {
+    let style = user
+        .avatar_style
+        .parse()
+        .unwrap_or(generated::Style::Identicon);
+    let content = generated::render(user.id.as_bytes(), &user.name, style, size)
+        .map_err(ResourceError::failed)?;
+
+    // Addressed by the user id, so the content changes with uploads and style settings.
+    Ok(
+        Cached::new(content, ImageFormat::Png.to_mime_type(), cache::REVALIDATE)
+            .respond(headers)
+            .await?,
+    )
+}
+
+async fn serve(
+    storage: &dyn Storage,
+    key: &str,
+    format: ImageFormat,
+    cache_control: &'static str,
+    headers: &HeaderMap,
+) -> Result<Response, ResourceError> {
+    let object = storage.get(key).await?;
+
+    Ok(
+        Cached::new(object.content, format.to_mime_type(), cache_control)
+            .with_last_modified(object.last_modified)
+            .respond(headers)
+            .await?,
+    )
+}
+
+async fn files(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Option<uuid::Uuid>>,
+    Path(file_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ResourceError> {
+    use diesel::prelude::*;
+
+    let file = db::execute_read(&state.database, user_id, move |conn| {
+        files::table
+            .find(Id(file_id))
+            .select(File::as_select())
+            .first(conn)
+            .optional()
+    })
+    .await?
+    .filter(|file| file.is_visible_to(user_id))
+    .ok_or(ResourceError::NotFound)?;
+
+    let hash = file.hash.as_deref().ok_or(ResourceError::NotFound)?;
+    let key = blobs::data_key(hash);
+
+    if state.config.load().storage.presigned_redirects {
+        let expires_in = Duration::from_secs(state.config.load().storage.presigned_expires_in);
+
+        if let Some(url) = state.storage.presign(&key, expires_in).await? {
+            return Ok(Redirect::temporary(&url).into_response());
+        }
+    }
+
+    let size = state.storage.head(&key).await?.size;
+    let cache_control = match file.visibility() {
+        Visibility::Private => cache::PRIVATE,
+        Visibility::Link | Visibility::Public => cache::REVALIDATE,
+    };
+
+    let mut response = Cached::stored(
+        state.storage.clone(),
+        key,
+        size,
+        hash,
+        file.mime.as_str(),
+        cache_control,
+    )
+    .with_last_modified(Some(file.created_at))
+    .respond(&headers)
+    .await?;
+
+    // Only render types that cannot run scripts in our origin.
+    let disposition = match file.mime.split('/').next() {
+        Some("image") if file.mime != "image/svg+xml" => "inline",
+        Some("video" | "audio") => "inline",
+        _ if file.mime == "application/pdf" => "inline",
+        _ => "attachment",
     };
+    let name = file.name.replace(['"', '\\', '\r', '\n'], "_");
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CONTENT_DISPOSITION,
+        header::HeaderValue::from_str(&format!("{}; filename=\"{}\"", disposition, name))
+            .unwrap_or(header::HeaderValue::from_static("attachment")),
+    );
+    response_headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        header::HeaderValue::from_static("nosniff"),
+    );
 
-    Ok(([(header::CONTENT_TYPE, mime)], bytes).into_response())
+    Ok(response)
 }
 
 #[derive(Debug)]
@@ -91,14 +339,59 @@ impl std::fmt::Display for ResourceError {
     }
 }
 
+impl From<StorageError> for ResourceError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::NotFound | StorageError::InvalidKey => Self::NotFound,
+            e => Self::failed(e),
+        }
+    }
+}
+
+impl From<DatabaseError> for ResourceError {
+    fn from(e: DatabaseError) -> Self {
+        Self::failed(e)
+    }
+}
+
+impl From<RepositoryError> for ResourceError {
+    fn from(e: RepositoryError) -> Self {
+        Self::failed(e)
+    }
+}
+
+impl ResourceError {
+    /// A failure on our side, logged with the id of the request since clients only get the id.
+    fn failed(e: impl std::fmt::Display) -> Self {
+        let error = Self::BadContent;
+        tracing::error!(
+            request_id = middleware::current_request_id().unwrap_or_default(),
+            "{}: {}",
+            error.code(),
+            e
+        );
+
+        error
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound | Self::NotExists => "resource.not_found",
+            Self::BadFormat => "resource.bad_format",
+            Self::BadContent => "resource.bad_content",
+        }
+    }
+}
+
 impl IntoResponse for ResourceError {
     fn into_response(self) -> Response {
         let status = match self {
             Self::NotFound => StatusCode::NOT_FOUND,
-            Self::NotExists => StatusCode::NO_CONTENT,
+            // A response without content cannot carry an error body.
+            Self::NotExists => return StatusCode::NO_CONTENT.into_response(),
             Self::BadFormat | Self::BadContent => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
-        (status, format!("{}", self)).into_response()
+        Problem::new(status, self.code(), &self).into_response()
     }
 }
@@ -0,0 +1,251 @@
+//! Runtime settings changed by administrators and stored in the database.
+//!
+//! Every setting has a default, taken from the configuration where one exists;
+//! only the values that differ are stored.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::{Config, Registration};
+use crate::db::{self, errors::DatabaseError, Pool};
+
+pub const MAX_SITE_NAME_LENGTH: usize = 64;
+pub const MAX_ANNOUNCEMENT_LENGTH: usize = 1000;
+
+/// Stored values by key, as cached in the application state.
+pub type Stored = HashMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Settings {
+    pub site_name: String,
+    pub registration: Registration,
+    /// Locale for visitors without a preference, e.g. `en` or `pt-BR`.
+    pub default_locale: String,
+    /// Maximum size of an uploaded avatar in bytes.
+    pub avatar_max_size: usize,
+    /// Maximum size of an uploaded file in bytes.
+    pub file_max_size: usize,
+    /// Total size of files every user may store, in bytes.
+    pub user_quota: u64,
+    /// Banner shown on every page, if any.
+    pub announcement: Option<String>,
+}
+
+/// A setting that was rejected, with the reason.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Invalid {
+    pub key: String,
+    pub message: String,
+}
+
+impl Invalid {
+    fn new(key: &str, message: impl Into<String>) -> Self {
+        Invalid {
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn defaults(config: &Config) -> Self {
+        Settings {
+            site_name: String::from("elnafo"),
+            registration: config.users.registration,
+            default_locale: String::from("en"),
+            avatar_max_size: config.uploads.avatar_max_size,
+            file_max_size: config.uploads.file_max_size,
+            user_quota: config.uploads.user_quota,
+            announcement: None,
+        }
+    }
+
+    /// Effective settings: the defaults overridden by the stored values.
+    ///
+    /// Stored values that no longer fit their setting are ignored.
+    pub fn resolve(config: &Config, stored: &Stored) -> Self {
+        let mut settings = Settings::defaults(config);
+
+        for (key, value) in stored {
+            if let Ok(merged) = settings.with(key, value.to_owned()) {
+                settings = merged;
+            }
+        }
+
+        settings
+    }
+
+    /// Applies changes, `null` restoring the default, and checks the result.
+    pub fn update(
+        config: &Config,
+        stored: &Stored,
+        changes: &serde_json::Map<String, Value>,
+    ) -> Result<Settings, Vec<Invalid>> {
+        let defaults = Settings::defaults(config);
+        let mut settings = Settings::resolve(config, stored);
+        let mut problems = Vec::new();
+
+        for (key, value) in changes {
+            let value = match value {
+                Value::Null => match defaults.get(key) {
+                    Some(value) => value,
+                    None => {
+                        problems.push(Invalid::new(key, "unknown setting"));
+                        continue;
+                    }
+                },
+                value => value.to_owned(),
+            };
+
+            match settings.with(key, value) {
+                Ok(merged) => settings = merged,
+                Err(problem) => problems.push(problem),
+            }
+        }
+
+        problems.extend(settings.check());
+
+        match problems.is_empty() {
+            true => Ok(settings),
+            false => Err(problems),
+        }
+    }
+
+    /// Values differing from the defaults, which are the ones to store.
+    pub fn changed(&self, config: &Config) -> Stored {
+        let (Ok(Value::Object(current)), Ok(Value::Object(defaults))) = (
+            serde_json::to_value(self),
+            serde_json::to_value(Settings::defaults(config)),
+        ) else {
+            return Stored::new();
+        };
+
+        current
+            .into_iter()
+            .filter(|(key, value)| defaults.get(key) != Some(value))
+            .collect()
+    }
+
+    fn get(&self, key: &str) -> Option<Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(mut map)) => map.remove(key),
+            _ => None,
+        }
+    }
+
+    fn with(&self, key: &str, value: Value) -> Result<Settings, Invalid> {
+        let Ok(Value::Object(mut map)) = serde_json::to_value(self) else {
+            return Err(Invalid::new(key, "failed to encode settings"));
+        };
+        if !map.contains_key(key) {
+            return Err(Invalid::new(key, "unknown setting"));
+        }
+
+        map.insert(key.to_string(), value);
+        serde_json::from_value(Value::Object(map)).map_err(|e| Invalid::new(key, e.to_string()))
+    }
+
+    fn check(&self) -> Vec<Invalid> {
+        let mut problems = Vec::new();
+
+        let site_name = self.site_name.trim();
+        if site_name.is_empty() || site_name.chars().count() > MAX_SITE_NAME_LENGTH {
+            problems.push(Invalid::new(
+                "site_name",
+                format!("must be 1 to {} characters long", MAX_SITE_NAME_LENGTH),
+            ));
+        }
+        if !is_locale(&self.default_locale) {
+            problems.push(Invalid::new(
+                "default_locale",
+                "must be a language code like `en` or `pt-BR`",
+            ));
+        }
+        if self.avatar_max_size == 0 {
+            problems.push(Invalid::new("avatar_max_size", "must be positive"));
+        }
+        if self.file_max_size == 0 {
+            problems.push(Invalid::new("file_max_size", "must be positive"));
+        }
+        if self.file_max_size as u64 > self.user_quota {
+            problems.push(Invalid::new("file_max_size", "exceeds user_quota"));
+        }
+        if let Some(announcement) = &self.announcement {
+            if announcement.chars().count() > MAX_ANNOUNCEMENT_LENGTH {
+                problems.push(Invalid::new(
+                    "announcement",
+                    format!(
+                        "must be at most {} characters long",
+                        MAX_ANNOUNCEMENT_LENGTH
+                    ),
+                ));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Reads the stored settings, skipping values that are not valid JSON.
+pub async fn load(pool: &Pool) -> Result<Stored, DatabaseError> {
+    let settings = db::execute(pool, db::setting::all).await?;
+
+    Ok(settings
+        .into_iter()
+        .filter_map(|setting| {
+            serde_json::from_str(&setting.value)
+                .ok()
+                .map(|value| (setting.key, value))
+        })
+        .collect())
+}
+
+fn is_locale(locale: &str) -> bool {
+    let (language, region) = match locale.split_once('-') {
+        Some((language, region)) => (language, Some(region)),
+        None => (locale, None),
+    };
+
+    let region = match region {
+        Some(region) => region.len() == 2 && region.chars().all(|c| c.is_ascii_uppercase()),
+        None => true,
+    };
+
+    (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_lowercase()) && region
+}
+
+#[test]
+fn test_update() {
+    let config = Config::default();
+    let mut stored = Stored::new();
+    stored.insert(String::from("site_name"), Value::from("Old"));
+
+    let changes = serde_json::json!({
+        "site_name": "New",
+        "default_locale": "pt-BR",
+        "registration": "closed",
+    });
+    let settings = Settings::update(&config, &stored, changes.as_object().unwrap()).unwrap();
+    assert_eq!(settings.site_name, "New");
+    assert_eq!(settings.registration, Registration::Closed);
+    assert_eq!(settings.changed(&config).len(), 3);
+
+    let changes = serde_json::json!({ "site_name": null, "registration": null });
+    let settings = Settings::update(&config, &stored, changes.as_object().unwrap()).unwrap();
+    assert_eq!(settings, Settings::defaults(&config));
+
+    let changes = serde_json::json!({
+        "site_name": "",
+        "user_quota": "lots",
+        "theme": "dark",
+        "file_max_size": 2048,
+    });
+    let keys = Settings::update(&config, &stored, changes.as_object().unwrap())
+        .unwrap_err()
+        .into_iter()
+        .map(|problem| problem.key)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["theme", "user_quota", "site_name"]);
+}
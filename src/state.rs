@@ -1,6 +1,83 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use arc_swap::ArcSwap;
+
 use crate::config::Config;
+use crate::repository::UserRepository;
+use crate::settings::{Settings, Stored};
+use crate::storage::Storage;
 
 pub struct AppState {
     pub database: crate::db::Pool,
-    pub config: Config,
+    /// Current configuration, swapped on reload; `load` it for every use.
+    pub config: ArcSwap<Config>,
+    pub storage: Arc<dyn Storage>,
+    pub users: Arc<dyn UserRepository>,
+    /// Cached runtime settings from the database, replaced whenever they are written.
+    pub settings: ArcSwap<Stored>,
+    /// When users were last recorded as seen by this process.
+    pub seen: Mutex<HashMap<uuid::Uuid, Instant>>,
+    /// Uploads a chunk is being appended to.
+    pub appending: crate::api::tus::Appending,
+}
+
+/// How often at most the last time a user was seen is recorded.
+pub const LAST_SEEN_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+impl AppState {
+    /// Effective runtime settings.
+    pub fn settings(&self) -> Settings {
+        Settings::resolve(&self.config.load(), &self.settings.load())
+    }
+
+    /// Marks the user as seen now, returning whether to record it: it was not recorded
+    /// in the last [`LAST_SEEN_INTERVAL`]. Older entries are dropped on the way.
+    pub fn mark_seen(&self, id: uuid::Uuid) -> bool {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        seen.retain(|_, last| now.duration_since(*last) < LAST_SEEN_INTERVAL);
+
+        match seen.contains_key(&id) {
+            true => false,
+            false => {
+                seen.insert(id, now);
+                true
+            }
+        }
+    }
+
+    /// Records in the background that the user made a request.
+    pub fn record_seen(&self, id: uuid::Uuid) {
+        let users = self.users.clone();
+        tokio::spawn(async move {
+            if let Err(e) = users.record_seen(id).await {
+                tracing::warn!("Failed to record user {} as seen: {}", id, e);
+            }
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_mark_seen() {
+    let config = Config::default();
+    let state = AppState {
+        database: crate::db::create_pool(&config).unwrap(),
+        storage: Arc::new(crate::storage::local::Local::new(std::env::temp_dir())),
+        users: Arc::new(crate::repository::memory::Memory::new()),
+        config: ArcSwap::from_pointee(config),
+        settings: ArcSwap::from_pointee(Default::default()),
+        seen: Default::default(),
+        appending: Default::default(),
+    };
+    let (stale, user) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+    state.seen.lock().unwrap().insert(
+        stale,
+        Instant::now().checked_sub(2 * LAST_SEEN_INTERVAL).unwrap(),
+    );
+
+    assert!(state.mark_seen(user));
+    assert!(!state.mark_seen(user));
+    assert!(!state.seen.lock().unwrap().contains_key(&stale));
 }
@@ -0,0 +1,132 @@
+use sha2::{Digest, Sha256};
+
+use crate::db::{self, schema::blobs};
+use crate::state::AppState;
+
+use super::{errors::StorageError, Storage};
+
+/// Hex encoded SHA-256 of the content, used as the blob identifier.
+pub fn hash(content: &[u8]) -> String {
+    hex(&Sha256::digest(content))
+}
+
+/// Same as [`hash`] for the content of a file, read in chunks.
+pub fn hash_file(path: &std::path::Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn is_hash(id: &str) -> bool {
+    id.len() == 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Storage key prefix of the blob, sharded by the first two bytes of the hash
+/// so no directory grows too large: `blobs/ab/cd/abcd...`.
+pub fn prefix(hash: &str) -> String {
+    format!("blobs/{}/{}/{}", &hash[0..2], &hash[2..4], hash)
+}
+
+/// Storage key of the raw blob content.
+pub fn data_key(hash: &str) -> String {
+    format!("{}/data", prefix(hash))
+}
+
+/// Deletes every blob no longer referenced, returning the hashes removed.
+///
+/// The rows go first, in a statement checking the count again, so a blob a concurrent
+/// `db::blob::acquire` took is kept. The content is deleted once that has committed,
+/// without holding locks across the calls to the storage.
+pub async fn collect_garbage(
+    pool: &db::Pool,
+    storage: std::sync::Arc<dyn Storage>,
+) -> Result<Vec<String>, StorageError> {
+    use diesel::prelude::*;
+
+    let hashes = db::transaction(pool, db::Isolation::ReadCommitted, |conn| {
+        diesel::delete(blobs::table.filter(blobs::ref_count.le(0)))
+            .returning(blobs::hash)
+            .get_results::<String>(conn)
+    })
+    .await?;
+
+    for hash in &hashes {
+        storage.delete_prefix(&prefix(hash)).await?;
+    }
+
+    Ok(hashes)
+}
+
+/// Drops a reference to the blob, such as one taken for an avatar or file that
+/// ended up not being assigned, and collects it once nothing refers to it.
+pub async fn release(state: &AppState, hash: String) {
+    match db::execute(&state.database, move |conn| db::blob::release(conn, &hash)).await {
+        Ok(Some(0)) => {
+            if let Err(e) = collect_garbage(&state.database, state.storage.clone()).await {
+                tracing::warn!("Failed to collect unreferenced blobs: {}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Failed to release blob: {}", e),
+    }
+}
+
+#[test]
+fn test_prefix() {
+    let hash = hash(b"elnafo");
+
+    assert!(is_hash(&hash));
+    assert!(!is_hash("86Rf07xd4z"));
+    assert_eq!(
+        prefix(&hash),
+        format!("blobs/{}/{}/{}", &hash[..2], &hash[2..4], hash)
+    );
+}
+
+#[tokio::test]
+async fn test_collect_garbage() {
+    let dir = std::env::temp_dir().join(format!("elnafo-blobs-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut config = crate::config::Config::default();
+    config.database.url = Some(format!("sqlite://{}", dir.join("elnafo.db").display()));
+    let pool = db::create_pool(&config).unwrap();
+    db::run_migrations(&pool).await.unwrap();
+    let storage: std::sync::Arc<dyn Storage> =
+        std::sync::Arc::new(super::local::Local::new(dir.join("storage")));
+
+    let kept = hash(b"kept");
+    let collected = hash(b"collected");
+    for hash in [&kept, &collected] {
+        storage
+            .put(&data_key(hash), hash.clone().into())
+            .await
+            .unwrap();
+    }
+
+    let hashes = [kept.clone(), collected.clone()];
+    db::execute(&pool, move |conn| {
+        for hash in &hashes {
+            db::blob::acquire(conn, hash, 64)?;
+            db::blob::release(conn, hash)?;
+        }
+        // Taken again before the collection, so the blob stays.
+        db::blob::acquire(conn, &hashes[0], 64)
+    })
+    .await
+    .unwrap();
+
+    let removed = collect_garbage(&pool, storage.clone()).await.unwrap();
+    assert!(storage.exists(&data_key(&kept)).await.unwrap());
+    assert!(!storage.exists(&data_key(&collected)).await.unwrap());
+    assert_eq!(removed, [collected]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
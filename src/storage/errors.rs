@@ -0,0 +1,58 @@
+use crate::db::errors::DatabaseError;
+use std::error::Error as StdError;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound,
+    InvalidKey,
+    IO(std::io::Error),
+    Backend(object_store::Error),
+    Config(String),
+    Database(DatabaseError),
+}
+
+impl StdError for StorageError {}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "Object was not found"),
+            Self::InvalidKey => write!(f, "Invalid object key"),
+            Self::IO(ref e) => e.fmt(f),
+            Self::Backend(ref e) => e.fmt(f),
+            Self::Config(ref e) => write!(f, "Invalid storage configuration: {}", e),
+            Self::Database(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Self::NotFound,
+            _ => Self::IO(e),
+        }
+    }
+}
+
+impl From<object_store::Error> for StorageError {
+    fn from(e: object_store::Error) -> Self {
+        match e {
+            object_store::Error::NotFound { .. } => Self::NotFound,
+            _ => Self::Backend(e),
+        }
+    }
+}
+
+impl From<DatabaseError> for StorageError {
+    fn from(e: DatabaseError) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<diesel::result::Error> for StorageError {
+    fn from(e: diesel::result::Error) -> Self {
+        Self::Database(DatabaseError::Query(e))
+    }
+}
@@ -0,0 +1,181 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, TryStreamExt};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::{errors::StorageError, validate_key, ByteStream, Meta, Object, Storage};
+
+/// Stores objects as plain files under a root directory.
+pub struct Local {
+    root: PathBuf,
+}
+
+impl Local {
+    pub fn new(root: PathBuf) -> Self {
+        Local { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        validate_key(key)?;
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for Local {
+    async fn put(&self, key: &str, content: Vec<u8>) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        let temp = temp_path(&path).await?;
+
+        if let Err(e) = tokio::fs::write(&temp, content).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e.into());
+        }
+
+        rename(&temp, &path).await
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        let temp = temp_path(&path).await?;
+
+        if let Err(e) = tokio::fs::copy(source, &temp).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e.into());
+        }
+
+        rename(&temp, &path).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Object, StorageError> {
+        let path = self.path(key)?;
+        let content = tokio::fs::read(&path).await?;
+        let last_modified = tokio::fs::metadata(&path)
+            .await?
+            .modified()
+            .ok()
+            .map(DateTime::<Utc>::from);
+
+        Ok(Object {
+            content,
+            last_modified,
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<Meta, StorageError> {
+        let metadata = tokio::fs::metadata(self.path(key)?).await?;
+
+        Ok(Meta {
+            size: metadata.len(),
+            last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream, StorageError> {
+        let mut file = tokio::fs::File::open(self.path(key)?).await?;
+        file.seek(std::io::SeekFrom::Start(range.start)).await?;
+
+        Ok(
+            tokio_util::io::ReaderStream::new(file.take(range.end - range.start))
+                .map_err(StorageError::from)
+                .boxed(),
+        )
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        Ok(tokio::fs::try_exists(self.path(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), StorageError> {
+        let path = self.path(prefix)?;
+
+        // Avatars uploaded before variants were introduced are single files.
+        if tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|metadata| metadata.is_file())
+        {
+            return self.delete(prefix).await;
+        }
+
+        match tokio::fs::remove_dir_all(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn presign(&self, _: &str, _: Duration) -> Result<Option<String>, StorageError> {
+        Ok(None)
+    }
+}
+
+/// Path next to `path` to write to first, so readers never see a partial file.
+async fn temp_path(path: &Path) -> Result<PathBuf, StorageError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    Ok(path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        uuid::Uuid::new_v4()
+    )))
+}
+
+async fn rename(temp: &Path, path: &Path) -> Result<(), StorageError> {
+    tokio::fs::rename(temp, path).await.map_err(|e| {
+        let _ = std::fs::remove_file(temp);
+        e.into()
+    })
+}
+
+#[tokio::test]
+async fn test_roundtrip() {
+    let root = std::env::temp_dir().join(format!("elnafo-storage-{}", uuid::Uuid::new_v4()));
+    let storage = Local::new(root.clone());
+
+    storage
+        .put("avatars/x/32.png", vec![1, 2, 3])
+        .await
+        .unwrap();
+    assert!(storage.exists("avatars/x/32.png").await.unwrap());
+    assert_eq!(
+        storage.get("avatars/x/32.png").await.unwrap().content,
+        [1, 2, 3]
+    );
+    assert_eq!(storage.head("avatars/x/32.png").await.unwrap().size, 3);
+    let range = storage
+        .get_range("avatars/x/32.png", 1..3)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(range.concat(), [2, 3]);
+    assert!(storage.get("../escape").await.is_err());
+
+    let source = root.join("source");
+    std::fs::write(&source, [4, 5]).unwrap();
+    storage.put_file("avatars/x/64.png", &source).await.unwrap();
+    assert_eq!(
+        storage.get("avatars/x/64.png").await.unwrap().content,
+        [4, 5]
+    );
+
+    storage.delete_prefix("avatars/x").await.unwrap();
+    assert!(!storage.exists("avatars/x/32.png").await.unwrap());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
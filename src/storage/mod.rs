@@ -0,0 +1,91 @@
+pub mod blobs;
+pub mod errors;
+pub mod local;
+pub mod s3;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use std::{ops::Range, path::Path, sync::Arc, time::Duration};
+
+use crate::config::{Config, StorageBackend};
+
+use errors::StorageError;
+
+/// Stored object content with the metadata needed for HTTP caching.
+pub struct Object {
+    pub content: Vec<u8>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Size and modification time of a stored object.
+pub struct Meta {
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Object content read as it is sent, for objects too large to hold in memory.
+pub type ByteStream = BoxStream<'static, Result<Bytes, StorageError>>;
+
+/// Backend for user-uploaded files, addressed by `/`-separated keys like `avatars/<id>/64.png`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, content: Vec<u8>) -> Result<(), StorageError>;
+
+    /// Stores the content of a local file without reading it into memory.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), StorageError>;
+
+    async fn get(&self, key: &str) -> Result<Object, StorageError>;
+
+    async fn head(&self, key: &str) -> Result<Meta, StorageError>;
+
+    /// Reads the bytes of the object within `range`, as they are sent.
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream, StorageError>;
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError>;
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Removes every object whose key starts with `prefix/`.
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), StorageError>;
+
+    /// Returns a time-limited URL the client can download the object from directly,
+    /// if the backend supports it.
+    async fn presign(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<Option<String>, StorageError>;
+}
+
+pub fn from_config(config: &Config) -> Result<Arc<dyn Storage>, StorageError> {
+    Ok(match config.storage.backend {
+        StorageBackend::Local => Arc::new(local::Local::new(
+            config
+                .paths
+                .uploads()
+                .map_err(|e| StorageError::Config(e.to_string()))?,
+        )),
+        StorageBackend::S3 => {
+            Arc::new(s3::S3::new(config.storage.s3.as_ref().ok_or_else(
+                || StorageError::Config(String::from("missing [storage.s3] section")),
+            )?)?)
+        }
+    })
+}
+
+/// Rejects keys that could escape the storage root.
+pub fn validate_key(key: &str) -> Result<(), StorageError> {
+    if key.is_empty()
+        || key.starts_with('/')
+        || key.contains('\\')
+        || key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Err(StorageError::InvalidKey);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path,
+    signer::Signer,
+    GetOptions, ObjectStore, WriteMultipart,
+};
+use std::{ops::Range, time::Duration};
+use tokio::io::AsyncReadExt;
+
+use crate::config;
+
+use super::{errors::StorageError, validate_key, ByteStream, Meta, Object, Storage};
+
+/// Size of the parts a local file is uploaded in.
+const PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Stores objects in an S3-compatible bucket (AWS, MinIO, Garage, ...).
+pub struct S3 {
+    store: AmazonS3,
+}
+
+impl S3 {
+    pub fn new(config: &config::S3) -> Result<Self, StorageError> {
+        let mut builder = AmazonS3Builder::new()
+            .with_region(&config.region)
+            .with_bucket_name(&config.bucket)
+            .with_access_key_id(&config.access_key)
+            .with_secret_access_key(&config.secret_key)
+            .with_virtual_hosted_style_request(!config.path_style);
+
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+
+        Ok(S3 {
+            store: builder.build()?,
+        })
+    }
+
+    fn path(key: &str) -> Result<Path, StorageError> {
+        validate_key(key)?;
+
+        Ok(Path::from(key))
+    }
+}
+
+#[async_trait]
+impl Storage for S3 {
+    async fn put(&self, key: &str, content: Vec<u8>) -> Result<(), StorageError> {
+        self.store.put(&Self::path(key)?, content.into()).await?;
+
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, source: &std::path::Path) -> Result<(), StorageError> {
+        let mut file = tokio::fs::File::open(source).await?;
+        let mut upload = WriteMultipart::new_with_chunk_size(
+            self.store.put_multipart(&Self::path(key)?).await?,
+            PART_SIZE,
+        );
+
+        let mut buffer = vec![0; PART_SIZE];
+        loop {
+            let read = match file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    let _ = upload.abort().await;
+                    return Err(e.into());
+                }
+            };
+            if let Err(e) = upload.wait_for_capacity(2).await {
+                let _ = upload.abort().await;
+                return Err(e.into());
+            }
+            upload.write(&buffer[..read]);
+        }
+
+        upload.finish().await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Object, StorageError> {
+        let result = self.store.get(&Self::path(key)?).await?;
+        let last_modified = Some(result.meta.last_modified);
+        let content = result.bytes().await?.to_vec();
+
+        Ok(Object {
+            content,
+            last_modified,
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<Meta, StorageError> {
+        let meta = self.store.head(&Self::path(key)?).await?;
+
+        Ok(Meta {
+            size: meta.size as u64,
+            last_modified: Some(meta.last_modified),
+        })
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream, StorageError> {
+        let options = GetOptions {
+            range: Some((range.start as usize..range.end as usize).into()),
+            ..GetOptions::default()
+        };
+        let result = self.store.get_opts(&Self::path(key)?, options).await?;
+
+        Ok(result.into_stream().map_err(StorageError::from).boxed())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        match self.store.head(&Self::path(key)?).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match self.store.delete(&Self::path(key)?).await {
+            Ok(_) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), StorageError> {
+        let keys = self
+            .store
+            .list(Some(&Self::path(prefix)?))
+            .map_ok(|meta| meta.location)
+            .try_collect::<Vec<Path>>()
+            .await?;
+
+        for key in keys {
+            self.store.delete(&key).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn presign(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<Option<String>, StorageError> {
+        let url = self
+            .store
+            .signed_url(axum::http::Method::GET, &Self::path(key)?, expires_in)
+            .await?;
+
+        Ok(Some(url.to_string()))
+    }
+}
+
+/// Runs against any S3-compatible server, e.g. `minio server /tmp/minio` with
+/// a `elnafo-test` bucket: `ELNAFO_TEST_S3_ENDPOINT=... cargo test -- --ignored`.
+#[tokio::test]
+#[ignore = "needs MinIO, set ELNAFO_TEST_S3_ENDPOINT"]
+async fn test_roundtrip() {
+    let endpoint =
+        std::env::var("ELNAFO_TEST_S3_ENDPOINT").expect("ELNAFO_TEST_S3_ENDPOINT is not set");
+    let env = |key: &str, default: &str| std::env::var(key).unwrap_or(default.to_string());
+
+    let storage = S3::new(&config::S3 {
+        endpoint: Some(endpoint),
+        region: env("ELNAFO_TEST_S3_REGION", "us-east-1"),
+        bucket: env("ELNAFO_TEST_S3_BUCKET", "elnafo-test"),
+        access_key: env("ELNAFO_TEST_S3_ACCESS_KEY", "minioadmin"),
+        secret_key: env("ELNAFO_TEST_S3_SECRET_KEY", "minioadmin"),
+        secret_key_file: None,
+        path_style: true,
+    })
+    .unwrap();
+
+    storage
+        .put("avatars/x/32.png", vec![1, 2, 3])
+        .await
+        .unwrap();
+    assert!(storage.exists("avatars/x/32.png").await.unwrap());
+    assert_eq!(
+        storage.get("avatars/x/32.png").await.unwrap().content,
+        [1, 2, 3]
+    );
+    assert_eq!(storage.head("avatars/x/32.png").await.unwrap().size, 3);
+    let range = storage
+        .get_range("avatars/x/32.png", 1..3)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(range.concat(), [2, 3]);
+    assert!(storage
+        .presign("avatars/x/32.png", Duration::from_secs(60))
+        .await
+        .unwrap()
+        .is_some());
+
+    storage.delete_prefix("avatars/x").await.unwrap();
+    assert!(!storage.exists("avatars/x/32.png").await.unwrap());
+}